@@ -0,0 +1,58 @@
+//! Headless decode throughput with and without resampling, to check that a file at the
+//! output rate skips the resampler's cost. Analysis runs the same in each case. Run with
+//! `cargo bench --bench resample`.
+
+use std::time::{Duration, Instant};
+
+use time2freq::audio::{AudioPlayer, PlaybackEvent, ResampleQuality};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const OUTPUT_RATE: u32 = 48_000;
+const SECONDS: u32 = 30;
+const RUNS: u32 = 3;
+
+/// Seconds of audio per second of wall time, playing `SECONDS` of a file at `input_rate`.
+fn throughput(input_rate: u32, quality: ResampleQuality) -> f64 {
+    let frames = (input_rate * SECONDS) as usize;
+    let input = common::write_stereo_wav(&format!("bench-{input_rate}"), input_rate, frames, |i| {
+        let sample = (i % 100) as f32 / 500.0 - 0.1;
+        [sample, -sample]
+    });
+
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut player = AudioPlayer::new_headless(OUTPUT_RATE, 1024, quality).unwrap();
+        let started = Instant::now();
+        player.play(input.clone());
+        let mut ended = false;
+        while !ended || player.buffered_analysis_frames() > 0 {
+            while let Some(event) = player.poll_event() {
+                ended |= matches!(event, PlaybackEvent::TrackEnded(_));
+            }
+            player.analyze(Duration::from_millis(100));
+        }
+        best = best.min(started.elapsed());
+    }
+
+    std::fs::remove_file(&input).unwrap();
+    SECONDS as f64 / best.as_secs_f64()
+}
+
+fn main() {
+    println!(
+        "48000 Hz, no resampler: {:.0}x real time",
+        throughput(OUTPUT_RATE, ResampleQuality::Fast)
+    );
+    for quality in [
+        ResampleQuality::Fast,
+        ResampleQuality::Balanced,
+        ResampleQuality::High,
+    ] {
+        println!(
+            "44100 Hz, {quality:?} resampler: {:.0}x real time",
+            throughput(44_100, quality)
+        );
+    }
+}
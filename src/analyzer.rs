@@ -0,0 +1,538 @@
+use std::{collections::VecDeque, time::Duration};
+
+use ebur128::{EbuR128, Mode};
+
+use crate::{
+    audio::{
+        rms, to_db, visual_db, AnalysisFrame, DEFAULT_ANALYSIS_WINDOW, DEFAULT_RMS_FAST,
+        DEFAULT_RMS_SLOW, RING_CHANNELS, SILENCE_DB,
+    },
+    error::{Result, Time2FreqError},
+    spectrum::{
+        log_buckets, split_bands, MagnitudeScale, SmoothingMode, Spectrum, WindowFn,
+        DEFAULT_BAND_RANGE, DEFAULT_CROSSOVERS, DEFAULT_FFT_SIZE,
+    },
+    AWeighting, AttackRelease, OnsetDetector,
+};
+
+/// Default time constant for smoothing the bass/mid/treble energies.
+const DEFAULT_BAND_SMOOTHING: Duration = Duration::from_millis(100);
+
+/// Spectrum display scaling: 60 dB of range below a full-scale sine.
+const DEFAULT_MAGNITUDE_SCALE: (MagnitudeScale, f32, f32) = (MagnitudeScale::Db, -60.0, 0.0);
+
+/// Quietest short-term loudness counted by the histogram, in LUFS.
+const HISTOGRAM_FLOOR_LUFS: f32 = -70.0;
+
+/// Short-term loudness sampled every 100ms over a track, in 1 LU bins.
+struct LoudnessHistogram {
+    bins: Vec<u32>,
+    pending_frames: usize,
+}
+
+impl LoudnessHistogram {
+    fn new() -> Self {
+        Self {
+            bins: vec![0; -HISTOGRAM_FLOOR_LUFS as usize],
+            pending_frames: 0,
+        }
+    }
+
+    fn record(&mut self, frames: usize, sample_rate: u32, ebur128: &EbuR128) {
+        let interval = sample_rate as usize / 10;
+        self.pending_frames += frames;
+
+        while self.pending_frames >= interval {
+            self.pending_frames -= interval;
+
+            if let Ok(loudness) = ebur128.loudness_shortterm() {
+                let loudness = loudness as f32;
+                if loudness.is_finite() && loudness >= HISTOGRAM_FLOOR_LUFS {
+                    let bin = ((loudness - HISTOGRAM_FLOOR_LUFS) as usize).min(self.bins.len() - 1);
+                    self.bins[bin] += 1;
+                }
+            }
+        }
+    }
+
+    fn bins(&self) -> Vec<(f32, u32)> {
+        self.bins
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (HISTOGRAM_FLOOR_LUFS + i as f32, *count))
+            .collect()
+    }
+}
+
+/// Loudness meter for the analysis's stereo, whatever the source's channel count.
+fn new_ebur128(sample_rate: u32) -> EbuR128 {
+    EbuR128::new(
+        RING_CHANNELS as u32,
+        sample_rate,
+        Mode::M | Mode::S | Mode::I | Mode::TRUE_PEAK,
+    )
+    .unwrap()
+}
+
+/// Levels, loudness, spectrum, bands, and onsets of a stream of interleaved stereo,
+/// however it's produced. `AudioPlayer` feeds one from its analysis buffer, but samples
+/// can come from anywhere: a file, a test signal, or the network.
+///
+/// Samples go in with `push_frames`. Levels and loudness are up to date as soon as it
+/// returns; the spectrum and what's derived from it are worked out when asked for.
+pub struct Analyzer {
+    sample_rate: u32,
+    rms: [f32; 2],
+    peak: [f32; 2],
+    true_peak: [f32; 2],
+    ebur128: EbuR128,
+    analyzed_frames: u64,
+    analysis_window: Duration,
+    /// The last `analysis_window` of analyzed samples per channel, for peak.
+    level_window: [VecDeque<f32>; 2],
+    /// The same span for RMS, after A-weighting if it's on.
+    rms_window: [VecDeque<f32>; 2],
+    a_weighting: Option<[AWeighting; 2]>,
+    /// RMS of each pushed chunk per channel, smoothed with fast and slow ballistics.
+    rms_fast: [AttackRelease; 2],
+    rms_slow: [AttackRelease; 2],
+    spectrum: Spectrum,
+    magnitude_scale: (MagnitudeScale, f32, f32),
+    scaled_spectrum: Vec<f32>,
+    histogram: LoudnessHistogram,
+    crossovers: (f32, f32),
+    band_smoothing: [AttackRelease; 3],
+    onset_detector: OnsetDetector,
+}
+
+impl Analyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut spectrum = Spectrum::new(DEFAULT_FFT_SIZE);
+        let nyquist = sample_rate as f32 / 2.0;
+        if DEFAULT_BAND_RANGE.1 > nyquist {
+            spectrum.set_band_range(DEFAULT_BAND_RANGE.0, nyquist);
+        }
+
+        Self {
+            sample_rate,
+            rms: [0., 0.],
+            peak: [0., 0.],
+            true_peak: [0., 0.],
+            ebur128: new_ebur128(sample_rate),
+            analyzed_frames: 0,
+            analysis_window: DEFAULT_ANALYSIS_WINDOW,
+            level_window: Default::default(),
+            rms_window: Default::default(),
+            a_weighting: None,
+            rms_fast: [AttackRelease::new(DEFAULT_RMS_FAST.0, DEFAULT_RMS_FAST.1); 2],
+            rms_slow: [AttackRelease::new(DEFAULT_RMS_SLOW.0, DEFAULT_RMS_SLOW.1); 2],
+            spectrum,
+            magnitude_scale: DEFAULT_MAGNITUDE_SCALE,
+            scaled_spectrum: Vec::new(),
+            histogram: LoudnessHistogram::new(),
+            crossovers: DEFAULT_CROSSOVERS,
+            band_smoothing: [AttackRelease::new(DEFAULT_BAND_SMOOTHING, DEFAULT_BAND_SMOOTHING); 3],
+            onset_detector: OnsetDetector::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Analyze interleaved stereo `samples`. A trailing half frame is ignored.
+    pub fn push_frames(&mut self, samples: &[f32]) {
+        let (mut l, mut r) = (vec![], vec![]);
+        for frame in samples.chunks_exact(RING_CHANNELS) {
+            self.spectrum.push((frame[0] + frame[1]) / 2.0);
+            l.push(frame[0]);
+            r.push(frame[1]);
+        }
+        if l.is_empty() {
+            return;
+        }
+        self.analyzed_frames += l.len() as u64;
+
+        debug_assert_eq!(self.ebur128.channels() as usize, RING_CHANNELS);
+        if let Err(e) = self.ebur128.add_frames_planar_f32(&[&l, &r]) {
+            log::error!("loudness analysis: {e}");
+        }
+        self.histogram
+            .record(l.len(), self.sample_rate, &self.ebur128);
+
+        // Smoothed by the audio analyzed rather than the frame time, so the meters keep
+        // time with the signal even when a frame comes up short.
+        let chunk = Duration::from_secs_f64(l.len() as f64 / self.sample_rate as f64);
+        let window_frames = self.analysis_window_frames();
+        for (channel, new) in [&l, &r].into_iter().enumerate() {
+            // Weighting shapes RMS only; peaks and loudness stay as they are.
+            let weighted = match &mut self.a_weighting {
+                Some(filters) => new
+                    .iter()
+                    .map(|sample| filters[channel].process(*sample))
+                    .collect(),
+                None => new.clone(),
+            };
+            let level = rms(&weighted);
+            self.rms_fast[channel].process(level, chunk);
+            self.rms_slow[channel].process(level, chunk);
+
+            let window = &mut self.rms_window[channel];
+            window.extend(weighted);
+            window.drain(..window.len().saturating_sub(window_frames));
+            self.rms[channel] = rms(window.make_contiguous());
+
+            let window = &mut self.level_window[channel];
+            window.extend(new);
+            window.drain(..window.len().saturating_sub(window_frames));
+            self.peak[channel] = window.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+        }
+        for (channel, true_peak) in self.true_peak.iter_mut().enumerate() {
+            *true_peak = self.ebur128.prev_true_peak(channel as u32).unwrap_or(0.0) as f32;
+        }
+    }
+
+    /// Move the clock on by `frames` that were dropped rather than analyzed, so
+    /// `timestamp` stays in step with the audio.
+    pub fn skip_frames(&mut self, frames: usize) {
+        self.analyzed_frames += frames as u64;
+    }
+
+    /// Audio-clock time of the end of the last pushed frame, from the count of frames
+    /// pushed or skipped.
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_secs_f64(self.analyzed_frames as f64 / self.sample_rate as f64)
+    }
+
+    /// The current levels and loudness, as of the last `push_frames`.
+    pub fn frame(&self) -> AnalysisFrame {
+        AnalysisFrame {
+            rms: self.rms,
+            rms_db: self.rms_db(),
+            peak: self.peak,
+            true_peak: self.true_peak,
+            loudness: self.loudness(),
+            timestamp: self.timestamp(),
+        }
+    }
+
+    /// Linear RMS per channel over the last `analysis_window`.
+    pub fn rms(&self) -> [f32; 2] {
+        self.rms
+    }
+
+    /// RMS of the last analyzed window in dBFS, at least `SILENCE_DB`.
+    pub fn rms_db(&self) -> [f32; 2] {
+        self.rms.map(to_db)
+    }
+
+    /// Largest absolute sample per channel in the last analyzed window.
+    pub fn peak(&self) -> [f32; 2] {
+        self.peak
+    }
+
+    /// Inter-sample peak per channel in the last analyzed window, from 4x oversampling.
+    pub fn true_peak(&self) -> [f32; 2] {
+        self.true_peak
+    }
+
+    /// Momentary loudness over the last 400ms in LUFS, at least `SILENCE_DB`.
+    pub fn loudness(&self) -> f32 {
+        // Momentary loudness of silence is -inf.
+        if let Ok(loudness) = self.ebur128.loudness_momentary() {
+            (loudness as f32).max(SILENCE_DB)
+        } else {
+            0.0
+        }
+    }
+
+    /// Loudness over the last 3 seconds in LUFS, or negative infinity in silence.
+    pub fn loudness_shortterm(&self) -> f32 {
+        self.ebur128
+            .loudness_shortterm()
+            .map_or(f32::NEG_INFINITY, |loudness| loudness as f32)
+    }
+
+    /// Gated loudness since the last `reset_loudness` in LUFS, or negative infinity in
+    /// silence.
+    pub fn loudness_integrated(&self) -> f32 {
+        self.ebur128
+            .loudness_global()
+            .map_or(f32::NEG_INFINITY, |loudness| loudness as f32)
+    }
+
+    /// Time spent at each short-term loudness since the last `reset_loudness`, as
+    /// `(lower bin edge in LUFS, count of 100ms readings)`.
+    pub fn loudness_histogram(&self) -> Vec<(f32, u32)> {
+        self.histogram.bins()
+    }
+
+    /// Start loudness measurement and the loudness histogram over, e.g. when a track
+    /// starts, so integrated loudness covers only that track.
+    pub fn reset_loudness(&mut self) {
+        self.ebur128 = new_ebur128(self.sample_rate);
+        self.histogram = LoudnessHistogram::new();
+    }
+
+    /// Forget everything about the audio so far from one track to the next: loudness,
+    /// onset history, and the weighting filters' state. Levels carry on.
+    pub fn start_track(&mut self) {
+        self.reset_loudness();
+        self.onset_detector.reset();
+        self.reset_a_weighting();
+    }
+
+    /// Zero all levels and drop everything analyzed, as if no audio had been pushed. The
+    /// clock and settings are kept.
+    pub fn reset(&mut self) {
+        self.rms = [0.0; 2];
+        self.peak = [0.0; 2];
+        self.true_peak = [0.0; 2];
+        self.level_window
+            .iter_mut()
+            .chain(&mut self.rms_window)
+            .for_each(VecDeque::clear);
+        self.rms_fast
+            .iter_mut()
+            .chain(&mut self.rms_slow)
+            .for_each(AttackRelease::reset);
+        self.spectrum.clear();
+        self.scaled_spectrum.iter_mut().for_each(|bin| *bin = 0.0);
+        self.band_smoothing
+            .iter_mut()
+            .for_each(AttackRelease::reset);
+        self.start_track();
+    }
+
+    /// RMS per channel smoothed with the fast meter's attack and release, as of the last
+    /// `push_frames`.
+    pub fn rms_fast(&self) -> [f32; 2] {
+        self.rms_fast.map(|meter| meter.value())
+    }
+
+    /// RMS per channel smoothed with the slow meter's attack and release, as of the last
+    /// `push_frames`.
+    pub fn rms_slow(&self) -> [f32; 2] {
+        self.rms_slow.map(|meter| meter.value())
+    }
+
+    /// The fast and slow meters on the same scale as the RMS from
+    /// `AnalysisFrame::visual_levels`.
+    pub fn visual_meters(&self) -> ([f32; 2], [f32; 2]) {
+        let visual = |rms: [f32; 2]| rms.map(|rms| visual_db(to_db(rms)));
+        (visual(self.rms_fast()), visual(self.rms_slow()))
+    }
+
+    /// Attack and release of the fast meter. Each meter moves `1 - 1/e`, about 63%, of
+    /// the way to a new level in one time constant of audio.
+    pub fn set_rms_fast(&mut self, attack: Duration, release: Duration) {
+        for meter in &mut self.rms_fast {
+            meter.set_times(attack, release);
+        }
+    }
+
+    pub fn rms_fast_times(&self) -> (Duration, Duration) {
+        self.rms_fast[0].times()
+    }
+
+    /// Attack and release of the slow meter.
+    pub fn set_rms_slow(&mut self, attack: Duration, release: Duration) {
+        for meter in &mut self.rms_slow {
+            meter.set_times(attack, release);
+        }
+    }
+
+    pub fn rms_slow_times(&self) -> (Duration, Duration) {
+        self.rms_slow[0].times()
+    }
+
+    /// A-weight the signal before measuring RMS, so levels follow how loud it sounds
+    /// rather than how much energy it has: bass counts for much less, 1 kHz the same.
+    /// Affects `rms`, `rms_db`, the meters, and `AnalysisFrame::visual_levels`, but not
+    /// peaks or loudness, which has its own weighting.
+    pub fn set_a_weighting(&mut self, on: bool) {
+        if on != self.a_weighting.is_some() {
+            self.a_weighting = on.then(|| {
+                [
+                    AWeighting::new(self.sample_rate),
+                    AWeighting::new(self.sample_rate),
+                ]
+            });
+        }
+    }
+
+    pub fn a_weighting(&self) -> bool {
+        self.a_weighting.is_some()
+    }
+
+    fn reset_a_weighting(&mut self) {
+        if let Some(filters) = &mut self.a_weighting {
+            filters.iter_mut().for_each(AWeighting::reset);
+        }
+    }
+
+    /// Measure RMS and peak over `window` of audio, at least one frame.
+    pub fn set_analysis_window(&mut self, window: Duration) {
+        self.analysis_window = window;
+        let window_frames = self.analysis_window_frames();
+        for samples in self.level_window.iter_mut().chain(&mut self.rms_window) {
+            samples.drain(..samples.len().saturating_sub(window_frames));
+        }
+    }
+
+    pub fn analysis_window(&self) -> Duration {
+        self.analysis_window
+    }
+
+    pub(crate) fn analysis_window_frames(&self) -> usize {
+        ((self.analysis_window.as_secs_f64() * self.sample_rate as f64).round() as usize).max(1)
+    }
+
+    /// Zero-pad the FFT to `factor` times the analysis window for finer bin spacing.
+    pub fn set_fft_zero_pad(&mut self, factor: usize) {
+        self.spectrum.set_zero_pad(factor);
+        log::info!(
+            "fft zero pad: {}x, bin width: {} Hz",
+            self.spectrum.zero_pad(),
+            self.spectrum.bin_width(self.sample_rate)
+        );
+    }
+
+    /// Take the spectrum over the last `fft_size` samples, which must be a power of two.
+    /// Bins are `sample_rate / fft_size` apart, so doubling the size halves their width
+    /// but doubles how far back the spectrum reaches, slowing its response.
+    pub fn set_fft_size(&mut self, fft_size: usize) -> Result<()> {
+        if !fft_size.is_power_of_two() || fft_size < 2 {
+            return Err(Time2FreqError::InvalidConfig(format!(
+                "fft size must be a power of two of at least 2, got {fft_size}"
+            )));
+        }
+        self.spectrum.set_fft_size(fft_size);
+        log::info!(
+            "fft size: {fft_size}, bin width: {} Hz, window: {:.1} ms",
+            self.spectrum.bin_width(self.sample_rate),
+            fft_size as f32 * 1000.0 / self.sample_rate as f32
+        );
+        Ok(())
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.spectrum.fft_size()
+    }
+
+    pub fn set_window(&mut self, window_fn: WindowFn) {
+        self.spectrum.set_window(window_fn);
+    }
+
+    pub fn window(&self) -> WindowFn {
+        self.spectrum.window()
+    }
+
+    /// Limit the log-spaced bands to `low_hz..=high_hz`, which must lie below Nyquist.
+    pub fn set_band_range(&mut self, low_hz: f32, high_hz: f32) -> Result<()> {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        if low_hz <= 0.0 || low_hz >= high_hz || high_hz > nyquist {
+            return Err(Time2FreqError::InvalidConfig(format!(
+                "band range must satisfy 0 < low < high <= {nyquist} Hz, got {low_hz}..{high_hz} Hz"
+            )));
+        }
+        self.spectrum.set_band_range(low_hz, high_hz);
+        Ok(())
+    }
+
+    /// Magnitude spectrum of the most recently pushed samples, smoothed over `dt` since
+    /// the last call as set by `set_spectrum_smoothing`, and mapped to `0.0..=1.0` by
+    /// `set_magnitude_scale`.
+    pub fn spectrum(&mut self, dt: Duration) -> &[f32] {
+        let (scale, floor, ceiling) = self.magnitude_scale;
+        let magnitudes = self.spectrum.process_smoothed(dt);
+        self.scaled_spectrum.clear();
+        self.scaled_spectrum.extend(
+            magnitudes
+                .iter()
+                .map(|magnitude| scale.apply(*magnitude, floor, ceiling)),
+        );
+        &self.scaled_spectrum
+    }
+
+    /// The last `spectrum` remapped into `n_buckets` log-spaced bars across the band
+    /// range, the usual bar visualizer layout. Call after `spectrum` each frame.
+    pub fn log_spectrum(&self, n_buckets: usize) -> Vec<f32> {
+        let (scale, floor, ceiling) = self.magnitude_scale;
+        let bin_width = self.spectrum.bin_width(self.sample_rate);
+        let edges = self.spectrum.band_edges(n_buckets);
+        let mut buckets = log_buckets(self.spectrum.smoothed(), bin_width, &edges);
+        for bucket in &mut buckets {
+            *bucket = scale.apply(*bucket, floor, ceiling);
+        }
+        buckets
+    }
+
+    /// How `spectrum` and `log_spectrum` map magnitudes to `0.0..=1.0`, with `floor` and
+    /// `ceiling` in the scale's units. Defaults to 60 dB below a full-scale sine.
+    pub fn set_magnitude_scale(
+        &mut self,
+        scale: MagnitudeScale,
+        floor: f32,
+        ceiling: f32,
+    ) -> Result<()> {
+        if floor.partial_cmp(&ceiling) != Some(std::cmp::Ordering::Less) {
+            return Err(Time2FreqError::InvalidConfig(format!(
+                "magnitude floor {floor} must be below the ceiling {ceiling}"
+            )));
+        }
+        self.magnitude_scale = (scale, floor, ceiling);
+        Ok(())
+    }
+
+    pub fn magnitude_scale(&self) -> (MagnitudeScale, f32, f32) {
+        self.magnitude_scale
+    }
+
+    pub fn set_spectrum_smoothing(&mut self, smoothing: SmoothingMode) {
+        self.spectrum.set_smoothing(smoothing);
+    }
+
+    /// Smoothed bass, mid, and treble energy of the most recently pushed samples, as
+    /// summed spectrum magnitudes. Call once per frame with the frame's `dt`.
+    pub fn bands(&mut self, dt: Duration) -> [f32; 3] {
+        let bin_width = self.spectrum.bin_width(self.sample_rate);
+        let bands = split_bands(self.spectrum.process(), bin_width, self.crossovers);
+
+        let mut smoothed = [0.0; 3];
+        for ((out, smoothing), band) in smoothed.iter_mut().zip(&mut self.band_smoothing).zip(bands)
+        {
+            *out = smoothing.process(band, dt);
+        }
+        smoothed
+    }
+
+    /// Split `bands` at `low_mid_hz` and `mid_high_hz`, which must lie below Nyquist.
+    pub fn set_crossovers(&mut self, low_mid_hz: f32, mid_high_hz: f32) -> Result<()> {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        if low_mid_hz <= 0.0 || low_mid_hz >= mid_high_hz || mid_high_hz > nyquist {
+            return Err(Time2FreqError::InvalidConfig(format!(
+                "crossovers must satisfy 0 < low < high <= {nyquist} Hz, got {low_mid_hz} and {mid_high_hz} Hz"
+            )));
+        }
+        self.crossovers = (low_mid_hz, mid_high_hz);
+        Ok(())
+    }
+
+    pub fn crossovers(&self) -> (f32, f32) {
+        self.crossovers
+    }
+
+    /// Time constant of the exponential smoothing applied by `bands`.
+    pub fn set_band_smoothing(&mut self, tau: Duration) {
+        for smoothing in &mut self.band_smoothing {
+            smoothing.set_times(tau, tau);
+        }
+    }
+
+    /// How much the most recently analyzed spectrum is a beat or onset, from 0 to 1.
+    /// Call once per frame with the frame's `dt`.
+    pub fn onset(&mut self, dt: Duration) -> f32 {
+        self.onset_detector.process(self.spectrum.process(), dt)
+    }
+}
@@ -1,219 +1,655 @@
-use std::{path::PathBuf, thread, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use cpal::{
     traits::{DeviceTrait, StreamTrait},
-    FromSample, SizedSample,
+    FromSample, Sample as _, SizedSample,
 };
-use crossbeam::channel;
-use ebur128::{EbuR128, Mode};
+use crossbeam::{atomic::AtomicCell, channel};
 use rubato::Resampler as _;
 
-use crate::resources::{AudioFile, CopyMethod};
+use crate::{
+    resources::{AudioFile, CopyMethod, Tags},
+    sink::{self, OutputSink},
+    Analyzer, Crossfade, Time2FreqError,
+};
+
+/// Default attack and release of the fast RMS meter, quick enough to follow transients.
+pub const DEFAULT_RMS_FAST: (Duration, Duration) =
+    (Duration::from_millis(10), Duration::from_millis(150));
+
+/// Default attack and release of the slow RMS meter, about the 300 ms of a VU meter.
+pub const DEFAULT_RMS_SLOW: (Duration, Duration) =
+    (Duration::from_millis(300), Duration::from_millis(300));
+
+/// Default span of audio the RMS and peak levels are measured over.
+pub const DEFAULT_ANALYSIS_WINDOW: Duration = Duration::from_millis(50);
 
 type ChannelBuf = Vec<Vec<f32>>;
 
+/// Decoded audio is interleaved stereo in the ring buffers.
+pub(crate) const RING_CHANNELS: usize = 2;
+
+/// Loudest allowed volume, leaving a little headroom above unity.
+pub const MAX_VOLUME: f32 = 1.25;
+
+/// Time for the applied volume to travel the full `0.0..=1.0` range.
+const VOLUME_RAMP: Duration = Duration::from_millis(5);
+
+/// An `f32` gain stored as bits so the output callback can read it lock-free.
+struct AtomicGain(AtomicU32);
+
+impl Default for AtomicGain {
+    fn default() -> Self {
+        Self(AtomicU32::new(1f32.to_bits()))
+    }
+}
+
+impl AtomicGain {
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Level in dBFS and how long it must hold before a track counts as finished.
+type SilenceThreshold = (f32, Duration);
+
+/// Trade resampling CPU cost against quality, for files whose rate differs from the
+/// output's.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// 64-tap sinc with linear interpolation between fewer sub-samples, at roughly a
+    /// quarter of the cost of `Balanced`. Aliasing near the top of the band is audible on
+    /// bright material, but barely changes what the visualizer shows.
+    Fast,
+    /// 256-tap sinc with linear interpolation. Transparent for listening on most material.
+    #[default]
+    Balanced,
+    /// 512-tap sinc with cubic interpolation and a steeper cutoff, at about twice the cost
+    /// of `Balanced`. For careful listening on a machine with CPU to spare.
+    HighQuality,
+}
+
+impl ResampleQuality {
+    fn parameters(self) -> rubato::InterpolationParameters {
+        let (sinc_len, f_cutoff, interpolation, oversampling_factor) = match self {
+            ResampleQuality::Fast => (64, 0.91, rubato::InterpolationType::Linear, 128),
+            ResampleQuality::Balanced => (256, 0.95, rubato::InterpolationType::Linear, 256),
+            ResampleQuality::HighQuality => (512, 0.97, rubato::InterpolationType::Cubic, 256),
+        };
+
+        rubato::InterpolationParameters {
+            sinc_len,
+            f_cutoff,
+            interpolation,
+            oversampling_factor,
+            window: rubato::WindowFunction::BlackmanHarris2,
+        }
+    }
+}
+
+impl std::str::FromStr for ResampleQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(ResampleQuality::Fast),
+            "balanced" => Ok(ResampleQuality::Balanced),
+            "high" => Ok(ResampleQuality::HighQuality),
+            _ => Err(format!("expected fast, balanced, or high, got {s:?}")),
+        }
+    }
+}
+
 struct Resampler {
     inner: rubato::SincFixedIn<f32>,
     buf_in: ChannelBuf,
     buf_out: ChannelBuf,
+    /// Output frames per input frame.
+    ratio: f64,
+    /// Output frames still to drop from the start, where the sinc filter's delay puts
+    /// silence ahead of the track.
+    skip: usize,
+    /// What `skip` starts from, in output frames.
+    delay: usize,
+    /// Input frames taken and output frames given so far, to end at the right length.
+    frames_in: u64,
+    frames_out: u64,
 }
 
-pub struct AudioPlayer {
-    #[allow(unused)]
-    stream: cpal::Stream,
+impl Resampler {
+    /// Resample one chunk of interleaved `input` into interleaved `output`.
+    fn process(&mut self, input: &[f32], channels: usize, output: &mut Vec<f32>) {
+        for buf in [&mut self.buf_in, &mut self.buf_out] {
+            for channel in buf {
+                channel.clear();
+            }
+        }
+
+        for frame in input.chunks(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.buf_in[channel].push(*sample);
+            }
+        }
+
+        self.inner
+            .process_into_buffer(&self.buf_in, &mut self.buf_out, None)
+            .unwrap();
+        self.frames_in += (input.len() / channels) as u64;
+
+        let frames = self.buf_out[0].len();
+        let skipped = self.skip.min(frames);
+        self.skip -= skipped;
+        output.clear();
+        for i in skipped..frames {
+            for channel in &self.buf_out {
+                output.push(channel[i]);
+            }
+        }
+        self.frames_out += (frames - skipped) as u64;
+    }
+
+    /// Forget the input so far, as after a seek, so what follows starts as cleanly as a
+    /// new track.
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.skip = self.delay;
+        self.frames_in = 0;
+        self.frames_out = 0;
+    }
+
+    /// Resample the `partial` chunk left at the end of a track, padding with silence
+    /// until the filter's delayed tail is out, and drop what came from the padding. The
+    /// whole track comes out `ratio` times as long as it went in.
+    fn finish(
+        &mut self,
+        partial: &[f32],
+        channels: usize,
+        chunk_size: usize,
+        output: &mut Vec<f32>,
+    ) {
+        let frames_in = self.frames_in + (partial.len() / channels) as u64;
+        let total = (frames_in as f64 * self.ratio).ceil() as u64;
+        let mut chunk = partial.to_vec();
+        let mut resampled = Vec::new();
+        output.clear();
+        while self.frames_out < total {
+            chunk.resize(chunk_size * channels, 0.0);
+            let before = self.frames_out;
+            self.process(&chunk, channels, &mut resampled);
+            let keep = (self.frames_out - before).min(total - before) as usize;
+            output.extend_from_slice(&resampled[..keep * channels]);
+            chunk.clear();
+        }
+        self.frames_in = frames_in;
+        self.frames_out = total;
+    }
+}
+
+/// Level reported for digital silence, in dBFS.
+pub const SILENCE_DB: f32 = -100.0;
+
+/// Smallest amplitude taken through a logarithm, so silence stays finite.
+const MIN_AMPLITUDE: f32 = 1e-10;
+
+/// Level at the bottom of the meters from `AnalysisFrame::visual_levels`, in dBFS. Full
+/// scale is the top.
+pub const VISUAL_FLOOR_DB: f32 = -20.0;
+
+/// Loudness at the bottom of the scale from `AnalysisFrame::visual_levels`, in LUFS.
+/// 0 LUFS is the top, so broadcast's -23 LUFS sits near -0.15 and streaming's -14 at 0.3.
+pub const VISUAL_LOUDNESS_FLOOR_LUFS: f32 = -40.0;
+
+/// Root mean square of `samples`, or zero if there are none.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Convert a linear amplitude to dBFS, bottoming out at `SILENCE_DB`.
+pub fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(MIN_AMPLITUDE).log10()).max(SILENCE_DB)
+}
+
+/// Levels measured over one analysis window.
+#[derive(Debug, Clone, Copy)]
+pub struct AnalysisFrame {
+    /// Linear RMS amplitude per channel over the analysis window.
+    pub rms: [f32; 2],
+    /// `rms` in dBFS.
+    pub rms_db: [f32; 2],
+    /// Largest absolute sample value per channel over the analysis window.
+    pub peak: [f32; 2],
+    /// Peak of the 4x oversampled signal per channel, catching peaks between samples.
+    pub true_peak: [f32; 2],
+    /// Momentary loudness in LUFS.
+    pub loudness: f32,
+    /// Audio-clock time at the end of the window, from the analyzed sample count.
+    pub timestamp: Duration,
+}
+
+impl AnalysisFrame {
+    /// Levels scaled to about `-1.0..=1.0` for the shader, as `(rms, loudness)`.
+    ///
+    /// RMS maps `VISUAL_FLOOR_DB` to -1 and full scale to 1, and loudness maps
+    /// `VISUAL_LOUDNESS_FLOOR_LUFS` to -1 and 0 LUFS to 1, both clamped to that range.
+    pub fn visual_levels(&self) -> ([f32; 2], f32) {
+        let rms = self.rms_db.map(visual_db);
+        let loudness = visual_scale(self.loudness, VISUAL_LOUDNESS_FLOOR_LUFS);
+        (rms, loudness)
+    }
+
+    /// Sample peaks on the same scale as the RMS from `visual_levels`.
+    pub fn visual_peak(&self) -> [f32; 2] {
+        self.peak.map(|peak| visual_db(to_db(peak)))
+    }
+}
+
+/// Map `VISUAL_FLOOR_DB..=0` dBFS onto `-1.0..=1.0`.
+pub(crate) fn visual_db(db: f32) -> f32 {
+    visual_scale(db, VISUAL_FLOOR_DB)
+}
+
+/// Map `floor..=0` onto `-1.0..=1.0`, clamping what's outside.
+fn visual_scale(level: f32, floor: f32) -> f32 {
+    (1.0 - 2.0 * level / floor).clamp(-1.0, 1.0)
+}
+
+/// Something the decode thread reports back about playback, polled with
+/// `AudioPlayer::poll_event`.
+#[derive(Debug, Clone)]
+pub enum PlaybackEvent {
+    /// The file couldn't be opened or isn't a supported format, with the reason.
+    LoadFailed(PathBuf, String),
+    /// Decoding of a track began. Its audio follows what's already buffered.
+    TrackStarted(PathBuf),
+    /// Tags of the track that just started, sent right after `TrackStarted`. Any of them
+    /// may be missing.
+    TrackInfo(PathBuf, Tags),
+    /// A track finished decoding, reached trailing silence, or was skipped.
+    TrackEnded(PathBuf),
+    /// The last queued track has played out of the device, not just finished decoding.
+    Finished,
+}
+
+/// State shared between the player, the decode thread, and the stream callback.
+#[derive(Default)]
+struct Shared {
+    shutdown: AtomicBool,
+    paused: AtomicBool,
+    volume: AtomicGain,
+    /// Set by the decoder after a seek until the output callback discards stale audio.
+    flush_device: AtomicBool,
+    flush_analysis: AtomicBool,
+    /// Samples pushed into and taken out of the device ring buffer, counting the pre-fill.
+    ring_written: AtomicU64,
+    ring_played: AtomicU64,
+    /// Value of `ring_played` at which the current track was at position zero.
+    track_start: AtomicI64,
+    duration: AtomicCell<Option<Duration>>,
+    scrubbing: AtomicBool,
+    non_finite_samples: AtomicUsize,
+    analysis_dropped: AtomicUsize,
+    underruns: AtomicUsize,
+    /// Set while the decoder is on a track, so running dry between or after tracks isn't
+    /// counted as an underrun.
+    track_active: AtomicBool,
+    end_on_silence: AtomicCell<Option<SilenceThreshold>>,
+    /// Set to end the current track early and move on to the next queued one.
+    skip: AtomicBool,
+    /// Start each track over when it ends instead of moving on.
+    looping: AtomicBool,
+    /// Overlap between the end of one track and the start of the next.
+    crossfade: AtomicCell<Duration>,
+    /// Make the decoder wait for room in the analysis buffer rather than drop samples.
+    lossless_analysis: AtomicBool,
+    /// How decoded audio is folded to the rings' stereo.
+    downmix: Mutex<Downmix>,
+}
+
+impl Shared {
+    /// Have analysis drop everything buffered, and with `device` the output too, waiting
+    /// until the output callback has done so.
+    fn flush(&self, device: bool) {
+        self.flush_analysis.store(true, Ordering::Relaxed);
+        if !device {
+            return;
+        }
+        self.flush_device.store(true, Ordering::Relaxed);
+        while self.flush_device.load(Ordering::Relaxed) && !self.shutdown.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// Handles for talking to the decode thread when playing files.
+struct Decoder {
     tx_play_song: channel::Sender<PathBuf>,
+    /// Kept to clear the queue when `play` replaces it.
+    rx_play_song: channel::Receiver<PathBuf>,
+    tx_seek: channel::Sender<Duration>,
+    tx_stop: channel::Sender<()>,
+    rx_events: channel::Receiver<PlaybackEvent>,
+    thread: thread::JoinHandle<()>,
+}
+
+pub struct AudioPlayer {
+    /// `None` when rendering to a file.
+    stream: Option<cpal::Stream>,
+    decoder: Option<Decoder>,
+    shared: Arc<Shared>,
     lvl_cons: rtrb::Consumer<f32>,
-    rms: [f32; 2],
     #[allow(dead_code)]
     rms_buf: Option<ChannelBuf>,
-    sample_rate: u32,
+    /// Channels of the device, which the analysis doesn't depend on.
     #[allow(dead_code)]
     channels: u32,
-    ebur128: EbuR128,
+    visual_gain: f32,
+    analyzer: Analyzer,
 }
 
 impl AudioPlayer {
+    /// Play through `device`, with a stream of `T`, the sample type `config` is for.
+    /// Normally the ring buffer starts with `latency_ms` of silence to give the decoder a
+    /// head start. Starting `paused` skips that pre-fill instead: the decoder fills the
+    /// buffer while paused, so the first `resume` is heard right away.
+    ///
+    /// Mixing happens in `f32` either way, converted to `T` as the last step with
+    /// `convert_output`. Only `f32` has been tried against real hardware; the integer
+    /// types are covered by tests of the conversion alone.
     pub fn new<T>(
         device: &cpal::Device,
         config: &cpal::StreamConfig,
         latency_ms: usize,
         chunk_size: usize,
-    ) -> anyhow::Result<Self>
+        resample_quality: ResampleQuality,
+        buffer_multiplier: usize,
+        paused: bool,
+    ) -> Result<Self, Time2FreqError>
     where
         T: SizedSample + FromSample<f32>,
     {
         let device_sample_rate = config.sample_rate.0;
         let device_channels = config.channels as u32;
 
-        let latency_frames =
-            (latency_ms as f32 * device_sample_rate as f32 / 1000.0).round() as u32;
-        let latency_samples = (latency_frames * device_channels) as usize;
+        let latency_samples = check_buffering(
+            latency_ms,
+            device_sample_rate,
+            device_channels as usize,
+            chunk_size,
+            buffer_multiplier,
+        )?;
+        let latency_frames = latency_samples / device_channels as usize;
 
         log::info!("device sample rate: {device_sample_rate}");
         log::info!("device channels: {device_channels}");
         log::info!("latency frames: {latency_frames}");
         log::info!("latency samples: {latency_samples}");
 
-        let (mut device_send, mut device_recv) = rtrb::RingBuffer::<f32>::new(latency_samples * 2);
-        let (mut analysis_send, analysis_recv) = rtrb::RingBuffer::<f32>::new(latency_samples * 2);
+        // Headroom beyond the latency pre-fill, so the decoder doesn't block on a full buffer.
+        let buffer_samples = latency_samples * buffer_multiplier;
+        let (mut device_send, mut device_recv) = rtrb::RingBuffer::<f32>::new(buffer_samples);
+        let (analysis_send, analysis_recv) = rtrb::RingBuffer::<f32>::new(buffer_samples);
 
-        for _ in 0..latency_samples {
-            device_send.push(0.0)?;
+        let prefill_samples = if paused { 0 } else { latency_samples };
+        for _ in 0..prefill_samples {
+            // Fits: `check_buffering` holds `buffer_multiplier` to at least 1.
+            device_send.push(0.0).unwrap();
             //analysis_send.push(0.0)?;
         }
 
-        let (tx_play_song, rx_play_song) = channel::unbounded::<PathBuf>();
-
-        // Spawn a thread to process audio files.
-        std::thread::spawn(move || {
-            while let Ok(song) = rx_play_song.recv() {
-                let mut audio = AudioFile::open(song).unwrap();
-                let mut audio_buf = Vec::<f32>::with_capacity(4 * chunk_size);
-                let mut resampler_final = Vec::new();
+        let shared = Arc::new(Shared::default());
+        shared.paused.store(paused, Ordering::Relaxed);
+        shared
+            .ring_written
+            .store(prefill_samples as u64, Ordering::Relaxed);
+        let device_sink = sink::Device::new(device_send);
+        let unparker = device_sink.unparker().clone();
+        let decoder = spawn_decoder(
+            device_sink,
+            analysis_send,
+            shared.clone(),
+            device_sample_rate,
+            latency_ms,
+            chunk_size,
+            resample_quality,
+        );
 
-                log::info!("audio channels: {}", audio.channels());
-                log::info!("audio sample rate: {}", audio.sample_rate());
+        let output_shared = shared.clone();
+        let mut applied_volume = 1.0;
+        let volume_step = 1.0 / (VOLUME_RAMP.as_secs_f32() * device_sample_rate as f32);
+        // Mix buffer for the callback, sized up front for the usual period so it rarely
+        // has to grow on the audio thread.
+        let mut mix = Vec::<f32>::with_capacity(latency_samples);
 
-                let mut resampler = {
-                    if audio.sample_rate() != device_sample_rate {
-                        let interpolation_params = rubato::InterpolationParameters {
-                            sinc_len: 256,
-                            f_cutoff: 0.95,
-                            interpolation: rubato::InterpolationType::Linear,
-                            oversampling_factor: 256,
-                            window: rubato::WindowFunction::BlackmanHarris2,
-                        };
-                        let inner = rubato::SincFixedIn::<f32>::new(
-                            device_sample_rate as f64 / audio.sample_rate() as f64,
-                            2.0,
-                            interpolation_params,
-                            chunk_size,
-                            audio.channels(),
-                        )
-                        .unwrap();
-
-                        let buf_in = inner.input_buffer_allocate();
-                        let buf_out = inner.output_buffer_allocate();
-                        log::info!(
-                            "buf_in: {} buf_out: {}",
-                            buf_in[0].capacity(),
-                            buf_out[0].capacity()
-                        );
+        // Create audio output stream.
+        let stream = device.build_output_stream(
+            config,
+            move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
+                mix.resize(output.len(), 0.0);
+                let data = &mut mix[..];
 
-                        Some(Resampler {
-                            inner,
-                            buf_in,
-                            buf_out,
-                        })
-                    } else {
-                        log::info!("NO REsampler");
-                        None
+                if output_shared.flush_device.load(Ordering::Relaxed) {
+                    let slots = device_recv.slots();
+                    if let Ok(stale) = device_recv.read_chunk(slots) {
+                        stale.commit_all();
+                        output_shared
+                            .ring_played
+                            .fetch_add(slots as u64, Ordering::Relaxed);
                     }
-                };
-
-                let chunk_size = audio.channels() * chunk_size;
-
-                loop {
-                    match audio.next_sample(CopyMethod::Interleaved) {
-                        Ok(Some(signal)) => {
-                            let output = {
-                                if let Some(ref mut resampler) = resampler {
-                                    audio_buf.extend(signal.samples());
-                                    if audio_buf.len() >= chunk_size {
-                                        // Clear resampler buffers.
-                                        for buf in [&mut resampler.buf_in, &mut resampler.buf_out] {
-                                            for channel in buf {
-                                                channel.clear();
-                                            }
-                                        }
-
-                                        // Drain and process incoming audio.
-                                        let mut chunk = audio_buf.drain(0..chunk_size);
-                                        for _ in 0..chunk_size / audio.channels() {
-                                            for channel in 0..audio.channels() {
-                                                resampler.buf_in[channel]
-                                                    .push(chunk.next().unwrap());
-                                            }
-                                        }
-
-                                        resampler
-                                            .inner
-                                            .process_into_buffer(
-                                                &resampler.buf_in,
-                                                &mut resampler.buf_out,
-                                                None,
-                                            )
-                                            .unwrap();
-                                    } else {
-                                        // Buffer not full - get more data.
-                                        continue;
-                                    }
-
-                                    resampler_final.clear();
-
-                                    for i in 0..resampler.buf_out[0].len() {
-                                        for channel in 0..audio.channels() {
-                                            resampler_final.push(resampler.buf_out[channel][i]);
-                                        }
-                                    }
-
-                                    resampler_final.as_ref()
-                                } else {
-                                    signal.samples()
-                                }
-                            };
-
-                            // Send output to ring buffers.
-                            for sample in output {
-                                loop {
-                                    if device_send.push(*sample).is_ok() {
-                                        if analysis_send.push(*sample).is_err() {
-                                            //log::info!("couldn't write to lvl ringbuffer");
-                                        }
-                                        break;
-                                    }
-                                    log::info!("sleep: {}", latency_ms);
-                                    thread::sleep(Duration::from_millis(latency_ms as u64 / 2));
-                                }
-                            }
-                        }
+                    output_shared.flush_device.store(false, Ordering::Relaxed);
+                    unparker.unpark();
+                }
 
-                        Ok(None) => {
-                            break;
-                        }
+                // Leave the buffered audio in place so playback resumes where it stopped.
+                if output_shared.paused.load(Ordering::Relaxed) {
+                    output.fill(T::EQUILIBRIUM);
+                    return;
+                }
 
-                        Err(e) => {
-                            log::error!("{e:?}");
-                            break;
-                        }
+                let frames = data.len() / device_channels as usize;
+                let filled = fill_output(data, device_channels as usize, &mut device_recv);
+                output_shared
+                    .ring_played
+                    .fetch_add((filled * RING_CHANNELS) as u64, Ordering::Relaxed);
+
+                // Wake the decoder if it's waiting for the room just made.
+                unparker.unpark();
+
+                let track_active = output_shared.track_active.load(Ordering::Relaxed);
+                if is_underrun(filled, frames, track_active) {
+                    log::warn!("input fell behind");
+                    output_shared.underruns.fetch_add(1, Ordering::Relaxed);
+                }
+
+                // Ramp toward the target gain to avoid zipper noise on sudden changes.
+                let volume = output_shared.volume.load();
+                for frame in data.chunks_mut(device_channels as usize) {
+                    applied_volume += (volume - applied_volume).clamp(-volume_step, volume_step);
+                    for sample in frame {
+                        *sample *= applied_volume;
                     }
                 }
 
-                log::info!("Song over");
-            }
-        });
+                mute_while_scrubbing(data, output_shared.scrubbing.load(Ordering::Relaxed));
 
-        // Create audio output stream.
-        let stream = device.build_output_stream(
+                convert_output(output, data);
+            },
+            move |err| {
+                log::error!("{err}");
+            },
+            None,
+        )?;
+
+        stream.play()?;
+
+        Ok(Self::from_parts(
+            Some(stream),
+            Some(decoder),
+            shared,
+            analysis_recv,
+            device_sample_rate,
+            device_channels,
+        ))
+    }
+
+    /// Decode into a WAV file at `sample_rate` instead of playing through a device.
+    ///
+    /// The file is written at the pace of real playback so the visuals run as they
+    /// would live, and what's analyzed matches what's written sample for sample.
+    pub fn new_wav(
+        path: impl AsRef<std::path::Path>,
+        sample_rate: u32,
+        latency_ms: usize,
+        chunk_size: usize,
+        resample_quality: ResampleQuality,
+    ) -> Result<Self, Time2FreqError> {
+        let latency = Duration::from_millis(latency_ms as u64);
+        let latency_samples =
+            check_buffering(latency_ms, sample_rate, RING_CHANNELS, chunk_size, 2)?;
+        let (analysis_send, analysis_recv) = rtrb::RingBuffer::<f32>::new(latency_samples * 2);
+
+        log::info!(
+            "rendering to {} at {sample_rate} Hz",
+            path.as_ref().display()
+        );
+
+        let shared = Arc::new(Shared::default());
+        let decoder = spawn_decoder(
+            sink::WavFile::create(path, sample_rate, latency)
+                .map_err(|e| Time2FreqError::OutputFailed(e.into()))?,
+            analysis_send,
+            shared.clone(),
+            sample_rate,
+            latency_ms,
+            chunk_size,
+            resample_quality,
+        );
+
+        Ok(Self::from_parts(
+            None,
+            Some(decoder),
+            shared,
+            analysis_recv,
+            sample_rate,
+            RING_CHANNELS as u32,
+        ))
+    }
+
+    /// Decode with no output at all, as fast as `analyze` consumes the samples.
+    ///
+    /// Nothing is dropped between the decoder and the analysis, so the same file always
+    /// analyzes the same way regardless of timing. Meant for tests and batch analysis;
+    /// call `analyze` in a loop, waiting on `buffered_analysis_frames` for full windows.
+    pub fn new_headless(
+        sample_rate: u32,
+        chunk_size: usize,
+        resample_quality: ResampleQuality,
+    ) -> Result<Self, Time2FreqError> {
+        const WAIT_MS: usize = 10;
+        if chunk_size == 0 {
+            return Err(Time2FreqError::InvalidConfig(
+                "chunk_size must be > 0".into(),
+            ));
+        }
+        let (analysis_send, analysis_recv) =
+            rtrb::RingBuffer::<f32>::new(sample_rate as usize / 10 * RING_CHANNELS);
+
+        let shared = Arc::new(Shared::default());
+        shared.lossless_analysis.store(true, Ordering::Relaxed);
+        let decoder = spawn_decoder(
+            sink::Null,
+            analysis_send,
+            shared.clone(),
+            sample_rate,
+            WAIT_MS,
+            chunk_size,
+            resample_quality,
+        );
+
+        Ok(Self::from_parts(
+            None,
+            Some(decoder),
+            shared,
+            analysis_recv,
+            sample_rate,
+            RING_CHANNELS as u32,
+        ))
+    }
+
+    /// Analyze interleaved stereo samples pushed into `buffer` by the caller, with no
+    /// decoder or output at all, so `play` has no effect.
+    ///
+    /// Like `new_headless`, nothing is skipped to keep up, so the same samples always
+    /// analyze the same way. Useful for feeding generated signals to the analysis in tests.
+    pub fn new_analysis(buffer: rtrb::Consumer<f32>, sample_rate: u32) -> Self {
+        let shared = Arc::new(Shared::default());
+        shared.lossless_analysis.store(true, Ordering::Relaxed);
+        Self::from_parts(
+            None,
+            None,
+            shared,
+            buffer,
+            sample_rate,
+            RING_CHANNELS as u32,
+        )
+    }
+
+    /// Analyze live input from a capture device instead of playing files.
+    ///
+    /// Samples go straight into the analysis buffer with no decode thread or output, so
+    /// `play` has no effect. Mono input is duplicated to both analysis channels.
+    pub fn new_input<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        latency_ms: usize,
+    ) -> Result<Self, Time2FreqError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let input_sample_rate = config.sample_rate.0;
+        let input_channels = config.channels as usize;
+
+        // No decoder chunks here, so only the latency can be wrong.
+        let latency_samples = check_buffering(latency_ms, input_sample_rate, RING_CHANNELS, 1, 1)?;
+        let (mut analysis_send, analysis_recv) = rtrb::RingBuffer::<f32>::new(latency_samples * 2);
+
+        log::info!("input sample rate: {input_sample_rate}");
+        log::info!("input channels: {input_channels}");
+
+        let shared = Arc::new(Shared::default());
+        let input_shared = shared.clone();
+
+        let stream = device.build_input_stream(
             config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut input_fell_behind = false;
-
-                for sample in data.chunks_mut(device_channels as usize) {
-                    if let Ok(chunk) = device_recv.read_chunk(2) {
-                        let mut chunk = chunk.into_iter();
-                        sample[0] = chunk.next().unwrap();
-                        sample[1] = chunk.next().unwrap();
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                for frame in data.chunks(input_channels) {
+                    let left = frame[0].to_sample::<f32>();
+                    let right = frame.get(1).map_or(left, |right| right.to_sample());
+                    // Drop input rather than block if the renderer isn't keeping up.
+                    if analysis_send.slots() >= 2 {
+                        analysis_send.push(left).unwrap();
+                        analysis_send.push(right).unwrap();
                     } else {
-                        input_fell_behind = true;
-                        sample[0] = 0.0;
-                        sample[1] = 0.0;
+                        input_shared
+                            .analysis_dropped
+                            .fetch_add(2, Ordering::Relaxed);
                     }
                 }
-
-                if input_fell_behind {
-                    log::warn!("input fell behind");
-                }
             },
             move |err| {
                 log::error!("{err}");
@@ -223,61 +659,1025 @@ impl AudioPlayer {
 
         stream.play()?;
 
-        let ebur128 = EbuR128::new(device_channels, device_sample_rate, Mode::M).unwrap();
+        Ok(Self::from_parts(
+            Some(stream),
+            None,
+            shared,
+            analysis_recv,
+            input_sample_rate,
+            2,
+        ))
+    }
 
-        Ok(Self {
+    fn from_parts(
+        stream: Option<cpal::Stream>,
+        decoder: Option<Decoder>,
+        shared: Arc<Shared>,
+        analysis_recv: rtrb::Consumer<f32>,
+        sample_rate: u32,
+        channels: u32,
+    ) -> Self {
+        Self {
             stream,
-            tx_play_song,
+            decoder,
+            shared,
             lvl_cons: analysis_recv,
-            rms: [0., 0.],
             rms_buf: None,
-            sample_rate: device_sample_rate,
-            channels: device_channels,
-            ebur128,
-        })
+            channels,
+            visual_gain: 1.0,
+            analyzer: Analyzer::new(sample_rate),
+        }
     }
 
+    /// Analyze the next `dt` of audio, returning linear RMS per channel and momentary
+    /// loudness.
     pub fn rms(&mut self, dt: Duration) -> ([f32; 2], f32) {
-        let buf_size = (dt.as_secs_f32() * self.sample_rate as f32).round() as usize;
+        let frame = self.analyze(dt);
+        (frame.rms, frame.loudness)
+    }
+
+    /// Analyze the next `dt` of audio and return just its meter levels, as
+    /// `AnalysisFrame::visual_levels`.
+    pub fn visual_levels(&mut self, dt: Duration) -> ([f32; 2], f32) {
+        self.analyze(dt).visual_levels()
+    }
+
+    /// What `analyze` feeds, for the spectrum, bands, meters, and loudness of the audio
+    /// taken so far.
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
 
-        let (mut l, mut r) = (vec![], vec![]);
+    pub fn analyzer_mut(&mut self) -> &mut Analyzer {
+        &mut self.analyzer
+    }
+
+    /// Frames decoded but not yet taken by `analyze`.
+    pub fn buffered_analysis_frames(&self) -> usize {
+        self.lvl_cons.slots() / RING_CHANNELS
+    }
+
+    /// Take the next `dt` of audio from the analysis buffer into the `analyzer`. RMS and
+    /// peak cover the last `analysis_window` of it however long `dt` is, so the meters
+    /// behave the same at any frame rate.
+    pub fn analyze(&mut self, dt: Duration) -> AnalysisFrame {
+        let buf_size = (dt.as_secs_f32() * self.analyzer.sample_rate() as f32).round() as usize;
 
-        while let Ok(chunk) = self.lvl_cons.read_chunk(2) {
+        let mut samples = Vec::with_capacity(buf_size * RING_CHANNELS);
+
+        if self.shared.flush_analysis.swap(false, Ordering::Relaxed) {
+            if let Ok(stale) = self.lvl_cons.read_chunk(self.lvl_cons.slots()) {
+                stale.commit_all();
+            }
+        }
+
+        // Hold the last frame while paused instead of draining what was decoded ahead.
+        let paused = self.shared.paused.load(Ordering::Relaxed);
+
+        if !paused && !self.shared.lossless_analysis.load(Ordering::Relaxed) {
+            self.skip_backlog(buf_size);
+        }
+
+        while !paused {
+            let Ok(chunk) = self.lvl_cons.read_chunk(RING_CHANNELS) else {
+                break;
+            };
             let mut chunk = chunk.into_iter();
-            l.push(chunk.next().unwrap().powi(2));
-            r.push(chunk.next().unwrap().powi(2));
-            if l.len() >= buf_size {
+            let left = chunk.next().unwrap_or(0.0) * self.visual_gain;
+            let right = chunk.next().unwrap_or(0.0) * self.visual_gain;
+            samples.extend([left, right]);
+            if samples.len() >= buf_size * RING_CHANNELS {
                 break;
             }
         }
 
         log::trace!(
-            "rms.len {} {} -- {}",
-            l.len(),
-            r.len(),
+            "rms.len {} -- {}",
+            samples.len() / RING_CHANNELS,
             self.lvl_cons.slots()
         );
 
-        if !l.is_empty() && !r.is_empty() {
-            self.ebur128.add_frames_planar_f32(&[&l, &r]).unwrap();
+        self.analyzer.push_frames(&samples);
+        self.analyzer.frame()
+    }
+
+    /// Drop analysis samples that fell more than one window behind what's audible, as when
+    /// frames stall, so the visuals catch up instead of lagging for the rest of the track.
+    /// What's still in the device buffer is ahead of the output and kept, along with the
+    /// `frames` about to be analyzed.
+    fn skip_backlog(&mut self, frames: usize) {
+        let written = self.shared.ring_written.load(Ordering::Relaxed);
+        let played = self.shared.ring_played.load(Ordering::Relaxed);
+        let lead = written.saturating_sub(played) as usize;
+        let keep = lead + (frames + self.analyzer.analysis_window_frames()) * RING_CHANNELS;
+
+        let slots = self.lvl_cons.slots();
+        let excess = slots.saturating_sub(keep) / RING_CHANNELS * RING_CHANNELS;
+        if excess == 0 {
+            return;
+        }
+        if let Ok(stale) = self.lvl_cons.read_chunk(excess) {
+            stale.commit_all();
+            log::warn!("analysis fell behind, skipped {excess} samples");
+            self.analyzer.skip_frames(excess / RING_CHANNELS);
+            self.shared
+                .analysis_dropped
+                .fetch_add(excess, Ordering::Relaxed);
+        }
+    }
+
+    /// Gain applied to the analysis levels only, leaving playback loudness untouched.
+    pub fn set_visual_gain(&mut self, gain: f32) {
+        self.visual_gain = gain.max(0.0);
+    }
+
+    pub fn visual_gain(&self) -> f32 {
+        self.visual_gain
+    }
+
+    /// Also end a track once it stays below `threshold_db` for `duration`, instead of only
+    /// at the end of the file. `None` (the default) disables this.
+    pub fn set_end_on_silence(&self, threshold: Option<(f32, Duration)>) {
+        self.shared.end_on_silence.store(threshold);
+    }
+
+    /// Playback gain, clamped to `0.0..=MAX_VOLUME`. Analysis taps the signal before this,
+    /// so it doesn't change what the visualizer sees.
+    pub fn set_volume(&self, gain: f32) {
+        self.shared.volume.store(gain.clamp(0.0, MAX_VOLUME));
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.shared.volume.load()
+    }
+
+    /// Output silence, keeping the stream and buffered audio intact. The decoder tops up a
+    /// device's buffer and then waits.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    /// Jump to `position` in the current track, discarding audio buffered from before.
+    pub fn seek(&self, position: Duration) {
+        match &self.decoder {
+            Some(decoder) => decoder.tx_seek.send(position).unwrap(),
+            None => log::warn!("no decoder running, ignoring seek"),
+        }
+    }
+
+    /// How far into the current track the audible output is.
+    pub fn position(&self) -> Duration {
+        let played = self.shared.ring_played.load(Ordering::Relaxed) as i64;
+        let samples = (played - self.shared.track_start.load(Ordering::Relaxed)).max(0);
+        Duration::from_secs_f64(
+            samples as f64 / RING_CHANNELS as f64 / self.analyzer.sample_rate() as f64,
+        )
+    }
+
+    /// Length of the current track, if the container reports it.
+    pub fn duration(&self) -> Option<Duration> {
+        self.shared.duration.load()
+    }
+
+    /// Mute the output while the user scrubs, letting decoding and analysis keep running so
+    /// the visuals preview the new position.
+    /// Cleared whenever a track starts or playback stops.
+    pub fn set_scrubbing(&self, scrubbing: bool) {
+        self.shared.scrubbing.store(scrubbing, Ordering::Relaxed);
+    }
+
+    pub fn is_scrubbing(&self) -> bool {
+        self.shared.scrubbing.load(Ordering::Relaxed)
+    }
+
+    /// Count of NaN or infinite decoded samples that were replaced with silence.
+    pub fn non_finite_samples(&self) -> usize {
+        self.shared.non_finite_samples.load(Ordering::Relaxed)
+    }
+
+    /// Samples that didn't fit in the analysis buffer, or were skipped to catch up with the
+    /// output, and were never analyzed.
+    pub fn dropped_samples(&self) -> usize {
+        self.shared.analysis_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Output callbacks that ran out of decoded audio and played silence.
+    pub fn underruns(&self) -> usize {
+        self.shared.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Play `song` right away, replacing the current track and anything queued. Files
+    /// that can't be opened are reported as `PlaybackEvent::LoadFailed` rather than
+    /// stopping the player.
+    pub fn play(&mut self, song: PathBuf) {
+        self.analyzer.reset_loudness();
+        match &self.decoder {
+            Some(decoder) => {
+                decoder.rx_play_song.try_iter().for_each(drop);
+                // Skip before sending, so the decoder can't clear the flag for the new song.
+                self.shared.skip.store(true, Ordering::Relaxed);
+                self.enqueue(song);
+            }
+            None => log::warn!("no decoder running, ignoring song"),
+        }
+    }
+
+    /// Queue `song` to play after the current track and anything already queued.
+    pub fn enqueue(&self, song: PathBuf) {
+        match &self.decoder {
+            Some(decoder) => {
+                if decoder.tx_play_song.send(song).is_err() {
+                    log::error!("decode thread is gone, ignoring song");
+                }
+            }
+            None => log::warn!("no decoder running, ignoring song"),
+        }
+    }
+
+    /// End the current track, drop the queue and everything buffered, and zero the levels,
+    /// leaving the player idle until the next `play` or `enqueue`. Unlike skipping past
+    /// the last track, this doesn't report `PlaybackEvent::Finished`.
+    pub fn stop(&mut self) {
+        match &self.decoder {
+            Some(decoder) => {
+                decoder.rx_play_song.try_iter().for_each(drop);
+                if decoder.tx_stop.send(()).is_err() {
+                    log::error!("decode thread is gone, ignoring stop");
+                }
+            }
+            None => log::warn!("no decoder running, ignoring stop"),
+        }
 
-            let lvl_l = l.iter().sum::<f32>() / l.len() as f32;
-            let lvl_r = r.iter().sum::<f32>() / r.len() as f32;
+        self.shared.duration.store(None);
+        self.shared.track_start.store(i64::MAX, Ordering::Relaxed);
+        self.shared.scrubbing.store(false, Ordering::Relaxed);
+        // Make room right away, in case lossless analysis has the decoder waiting on it.
+        if let Ok(stale) = self.lvl_cons.read_chunk(self.lvl_cons.slots()) {
+            stale.commit_all();
+        }
+        self.shared.flush_analysis.store(true, Ordering::Relaxed);
+        self.analyzer.reset();
+    }
+
+    /// Fold decoded audio to stereo with `downmix` from the next chunk on, for playback
+    /// and analysis alike. Needed for sensible levels from surround files, e.g. with
+    /// `Downmix::itu_5_1`.
+    pub fn set_downmix(&self, downmix: Downmix) {
+        *self.shared.downmix.lock().unwrap() = downmix;
+    }
+
+    pub fn downmix(&self) -> Downmix {
+        self.shared.downmix.lock().unwrap().clone()
+    }
+
+    /// Fade each track into the next over `crossfade`, instead of playing them back to
+    /// back. Takes effect from the next track. Skipping still cuts straight over.
+    pub fn set_crossfade(&self, crossfade: Duration) {
+        self.shared.crossfade.store(crossfade);
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.shared.crossfade.load()
+    }
+
+    /// Replay each track from the start when it ends, until turned off or skipped.
+    pub fn set_loop(&self, looping: bool) {
+        self.shared.looping.store(looping, Ordering::Relaxed);
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.shared.looping.load(Ordering::Relaxed)
+    }
+
+    /// End the current track now and move on to the next queued one.
+    pub fn skip(&self) {
+        self.shared.skip.store(true, Ordering::Relaxed);
+    }
 
-            self.rms = [lvl_l, lvl_r];
-            //self.rms = [lvl_l.sqrt(), lvl_r.sqrt()];
+    /// Next pending event from the decode thread, if any. Loudness measurement and onset
+    /// detection restart when a `TrackStarted` comes through.
+    pub fn poll_event(&mut self) -> Option<PlaybackEvent> {
+        let event = self.decoder.as_ref()?.rx_events.try_recv().ok()?;
+        if let PlaybackEvent::TrackStarted(_) = event {
+            self.analyzer.start_track();
         }
+        Some(event)
+    }
+}
+
+/// Replace NaN and infinite samples with silence, returning how many were replaced.
+pub fn sanitize(samples: &mut [f32]) -> usize {
+    let mut replaced = 0;
+    for sample in samples.iter_mut().filter(|sample| !sample.is_finite()) {
+        *sample = 0.0;
+        replaced += 1;
+    }
+    replaced
+}
+
+/// Convert the `f32` mix to the device's sample type, full scale to full scale.
+pub fn convert_output<T>(output: &mut [T], mix: &[f32])
+where
+    T: SizedSample + FromSample<f32>,
+{
+    for (out, sample) in output.iter_mut().zip(mix) {
+        *out = T::from_sample(*sample);
+    }
+}
+
+/// Whether an output callback that got `filled` of its `frames` from the ring ran short of
+/// audio that was due. Running dry with no track playing, before the first, after the
+/// last, or after a stop, is just idling.
+pub fn is_underrun(filled: usize, frames: usize, track_active: bool) -> bool {
+    track_active && filled < frames
+}
 
-        let loudness = if let Ok(loudness) = self.ebur128.loudness_momentary() {
-            loudness as f32
+/// Silence a device buffer while the user scrubs. The ring is still consumed, so analysis
+/// follows along.
+pub fn mute_while_scrubbing(data: &mut [f32], scrubbing: bool) {
+    if scrubbing {
+        data.fill(0.0);
+    }
+}
+
+/// Fill an interleaved device buffer with stereo frames from `source`, mixed to the
+/// device's channel count with `upmix`.
+///
+/// Whole frames are zeroed once the source runs dry. Returns how many frames came from
+/// the source.
+pub fn fill_output(data: &mut [f32], channels: usize, source: &mut rtrb::Consumer<f32>) -> usize {
+    let mut filled = 0;
+
+    for frame in data.chunks_mut(channels) {
+        if let Ok(chunk) = source.read_chunk(RING_CHANNELS) {
+            let (first, second) = chunk.as_slices();
+            let mut input = first.iter().chain(second).copied();
+            let (left, right) = (input.next().unwrap(), input.next().unwrap());
+            upmix(frame, left, right);
+            chunk.commit_all();
+            filled += 1;
         } else {
-            0.0
+            frame.fill(0.0);
+        }
+    }
+
+    filled
+}
+
+/// Spread a stereo sample over one device frame.
+///
+/// Mono gets the average of both sides. With more channels, front left and right get the
+/// stereo pair, a center channel (index 2 in 3.0, 5.0, 5.1, and 7.1 layouts) gets the
+/// average, an LFE channel (index 3 in 5.1 and 7.1) stays silent, and any remaining
+/// channels alternate left and right.
+pub fn upmix(frame: &mut [f32], left: f32, right: f32) {
+    let channels = frame.len();
+    if channels == 1 {
+        frame[0] = (left + right) / 2.0;
+        return;
+    }
+
+    let has_center = matches!(channels, 3 | 5 | 6 | 8);
+    let has_lfe = matches!(channels, 6 | 8);
+
+    for (i, sample) in frame.iter_mut().enumerate() {
+        *sample = match i {
+            2 if has_center => (left + right) / 2.0,
+            3 if has_lfe => 0.0,
+            _ if i % 2 == 0 => left,
+            _ => right,
         };
+    }
+}
+
+/// Fold one frame of any channel count down to a stereo pair, the inverse of `upmix`.
+///
+/// Mono is copied to both sides and stereo passes through. Wider layouts average the
+/// channels `upmix` would feed from each side, with a center channel counted on both and
+/// an LFE channel left out. An empty frame is silence.
+pub fn downmix(frame: &[f32]) -> (f32, f32) {
+    match *frame {
+        [] => (0.0, 0.0),
+        [mono] => (mono, mono),
+        [left, right] => (left, right),
+        _ => {
+            let has_center = matches!(frame.len(), 3 | 5 | 6 | 8);
+            let has_lfe = matches!(frame.len(), 6 | 8);
+            let (mut left, mut right) = ((0.0, 0), (0.0, 0));
 
-        (self.rms, loudness)
+            for (i, sample) in frame.iter().enumerate() {
+                match i {
+                    2 if has_center => {
+                        for side in [&mut left, &mut right] {
+                            side.0 += sample;
+                            side.1 += 1;
+                        }
+                    }
+                    3 if has_lfe => {}
+                    _ if i % 2 == 0 => {
+                        left.0 += sample;
+                        left.1 += 1;
+                    }
+                    _ => {
+                        right.0 += sample;
+                        right.1 += 1;
+                    }
+                }
+            }
+
+            (left.0 / left.1 as f32, right.0 / right.1 as f32)
+        }
     }
+}
+
+/// How decoded frames are folded to stereo for playback and analysis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Downmix {
+    /// As `downmix` does it: stereo passes through and wider layouts average each side.
+    #[default]
+    Stereo,
+    /// The average of both sides of `Stereo`, on both sides.
+    Mono,
+    /// Per source channel, its gain into left and right. Channels past the end of the
+    /// list are left out.
+    Custom(Vec<[f32; 2]>),
+}
+
+impl Downmix {
+    /// ITU-R BS.775 for 5.1 in FL, FR, C, LFE, SL, SR order: center and surrounds at
+    /// -3 dB on their sides, LFE left out. Unlike `Stereo` it sums rather than averages,
+    /// so loud surround content can go past full scale.
+    pub fn itu_5_1() -> Self {
+        let g = std::f32::consts::FRAC_1_SQRT_2;
+        Self::Custom(vec![
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [g, g],
+            [0.0, 0.0],
+            [g, 0.0],
+            [0.0, g],
+        ])
+    }
+
+    /// Fold one frame to a stereo pair.
+    pub fn apply(&self, frame: &[f32]) -> (f32, f32) {
+        match self {
+            Self::Stereo => downmix(frame),
+            Self::Mono => {
+                let (left, right) = downmix(frame);
+                let mid = (left + right) / 2.0;
+                (mid, mid)
+            }
+            Self::Custom(gains) => frame.iter().zip(gains).fold(
+                (0.0, 0.0),
+                |(left, right), (sample, [to_left, to_right])| {
+                    (left + sample * to_left, right + sample * to_right)
+                },
+            ),
+        }
+    }
+}
+
+impl std::str::FromStr for Downmix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stereo" => Ok(Downmix::Stereo),
+            "mono" => Ok(Downmix::Mono),
+            "itu" => Ok(Downmix::itu_5_1()),
+            _ => Err(format!("expected stereo, mono, or itu, got {s:?}")),
+        }
+    }
+}
+
+/// Start the thread that decodes queued files into `sink` and the analysis buffer.
+fn spawn_decoder(
+    mut sink: impl OutputSink + 'static,
+    mut analysis_send: rtrb::Producer<f32>,
+    decode_shared: Arc<Shared>,
+    sample_rate: u32,
+    latency_ms: usize,
+    chunk_size: usize,
+    resample_quality: ResampleQuality,
+) -> Decoder {
+    let (tx_play_song, rx_play_song) = channel::unbounded::<PathBuf>();
+    let (tx_seek, rx_seek) = channel::unbounded::<Duration>();
+    let (tx_stop, rx_stop) = channel::unbounded::<()>();
+    let (tx_events, rx_events) = channel::unbounded::<PlaybackEvent>();
+    let queue = rx_play_song.clone();
+    // Lets a stream stalled on the network give way to a stop, skip, or shutdown.
+    let cancelled = {
+        let shared = decode_shared.clone();
+        let rx_stop = rx_stop.clone();
+        move || {
+            shared.shutdown.load(Ordering::Relaxed)
+                || shared.skip.load(Ordering::Relaxed)
+                || !rx_stop.is_empty()
+        }
+    };
+
+    // Spawn a thread to process audio files.
+    let thread = thread::spawn(move || {
+        let mut crossfade = Crossfade::default();
+        let mut faded = Vec::new();
+
+        while let Ok(song) = queue.recv() {
+            decode_shared.skip.store(false, Ordering::Relaxed);
+            // A stop while idle was meant for the track before this one.
+            rx_stop.try_iter().for_each(drop);
+
+            let opened = AudioFile::open_cancellable(&song, cancelled.clone()).and_then(|audio| {
+                let resampler = new_resampler(&audio, sample_rate, resample_quality, chunk_size)?;
+                Ok((audio, resampler))
+            });
+            let (mut audio, mut resampler) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    log::error!("can't play {}: {e}", song.display());
+                    let _ = tx_events.send(PlaybackEvent::LoadFailed(song, e.to_string()));
+                    // Nothing left to fade into, and nothing left to play.
+                    if queue.is_empty() {
+                        crossfade.drain(&mut faded);
+                        send_output(
+                            &faded,
+                            &mut sink,
+                            &mut analysis_send,
+                            &decode_shared,
+                            latency_ms,
+                        );
+                        if !finish_when_played(
+                            &decode_shared,
+                            &queue,
+                            &rx_stop,
+                            &tx_events,
+                            latency_ms,
+                        ) {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            };
+            // Only grown when resampling. At a matching rate the decoder's buffer goes to
+            // the output as it is.
+            let mut audio_buf = Vec::<f32>::new();
+            let mut resampler_final = Vec::new();
+            let mut stereo = Vec::new();
+
+            log::info!("audio channels: {}", audio.channels());
+            log::info!("audio sample rate: {}", audio.sample_rate());
+
+            let fade_frames = decode_shared.crossfade.load().as_secs_f64() * sample_rate as f64;
+            crossfade.set_fade_len(fade_frames.round() as usize * RING_CHANNELS);
+            start_track(&decode_shared, &tx_events, &song, &audio);
+
+            // `chunk_size` is in frames, as the resampler takes it; its input is buffered
+            // interleaved.
+            let chunk_samples = audio.channels() * chunk_size;
+            let mut silent_frames = 0usize;
+            let mut largest_output = 0usize;
+            let mut decoded_this_pass = false;
+            let mut stopped = false;
+            let mut skipped = false;
 
-    pub fn play(&self, song: PathBuf) {
-        self.tx_play_song.send(song).unwrap();
+            loop {
+                if decode_shared.shutdown.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                if rx_stop.try_iter().count() > 0 {
+                    log::info!("stopping");
+                    crossfade.clear();
+                    decode_shared.flush(sink.buffers());
+                    stopped = true;
+                    break;
+                }
+
+                if decode_shared.skip.swap(false, Ordering::Relaxed) {
+                    log::info!("skipping track");
+                    crossfade.clear();
+                    decode_shared.flush(sink.buffers());
+                    skipped = true;
+                    break;
+                }
+
+                if let Some(position) = rx_seek.try_iter().last() {
+                    match audio.seek(position) {
+                        Ok(()) => {
+                            audio_buf.clear();
+                            crossfade.clear();
+                            if let Some(ref mut resampler) = resampler {
+                                resampler.reset();
+                            }
+
+                            // Wait for the output to drop what's buffered from before.
+                            decode_shared.flush(sink.buffers());
+
+                            let written = decode_shared.ring_written.load(Ordering::Relaxed);
+                            let offset =
+                                position.as_secs_f64() * sample_rate as f64 * RING_CHANNELS as f64;
+                            decode_shared
+                                .track_start
+                                .store(written as i64 - offset as i64, Ordering::Relaxed);
+                        }
+                        Err(e) => log::error!("{e}"),
+                    }
+                }
+
+                // A buffering sink keeps filling while paused so playback resumes instantly,
+                // stopping short of blocking on a full buffer so seeks still get handled.
+                if decode_shared.paused.load(Ordering::Relaxed)
+                    && (!sink.buffers() || sink.space() < largest_output)
+                {
+                    thread::sleep(Duration::from_millis(latency_ms as u64 / 2));
+                    continue;
+                }
+
+                match audio.next_sample(CopyMethod::Interleaved) {
+                    Ok(Some(mut signal)) => {
+                        decoded_this_pass = true;
+                        let replaced = sanitize(signal.samples_mut());
+                        if replaced > 0 {
+                            log::warn!("replaced {replaced} non-finite samples");
+                            decode_shared
+                                .non_finite_samples
+                                .fetch_add(replaced, Ordering::Relaxed);
+                        }
+
+                        if let Some((threshold_db, duration)) = decode_shared.end_on_silence.load()
+                        {
+                            let peak = signal
+                                .samples()
+                                .iter()
+                                .fold(0f32, |peak, sample| peak.max(sample.abs()));
+
+                            if 20. * peak.log10() < threshold_db {
+                                silent_frames += signal.samples().len() / audio.channels();
+                            } else {
+                                silent_frames = 0;
+                            }
+
+                            let limit = duration.as_secs_f32() * audio.sample_rate() as f32;
+                            if silent_frames as f32 >= limit {
+                                log::info!("trailing silence, ending track");
+                                break;
+                            }
+                        }
+
+                        let output = if let Some(ref mut resampler) = resampler {
+                            audio_buf.extend(signal.samples());
+                            if audio_buf.len() < chunk_samples {
+                                // Buffer not full - get more data.
+                                continue;
+                            }
+                            resampler.process(
+                                &audio_buf[..chunk_samples],
+                                audio.channels(),
+                                &mut resampler_final,
+                            );
+                            audio_buf.drain(..chunk_samples);
+                            resampler_final.as_ref()
+                        } else {
+                            signal.samples()
+                        };
+
+                        let output = fold_to_stereo(
+                            output,
+                            audio.channels(),
+                            &decode_shared.downmix.lock().unwrap(),
+                            &mut stereo,
+                        );
+                        largest_output = largest_output.max(output.len());
+                        crossfade.process(output, &mut faded);
+                        if !send_output(
+                            &faded,
+                            &mut sink,
+                            &mut analysis_send,
+                            &decode_shared,
+                            latency_ms,
+                        ) {
+                            break;
+                        }
+                    }
+
+                    result => {
+                        if let Err(e) = result {
+                            log::error!("{e:?}");
+                        }
+
+                        // Start over from a fresh open, keeping the resampler and any
+                        // partial chunk so the loop point is seamless. The same file has
+                        // the same format, so the resampler still fits.
+                        if decode_shared.looping.load(Ordering::Relaxed) && decoded_this_pass {
+                            match AudioFile::open_cancellable(&song, cancelled.clone()) {
+                                Ok(reopened) => {
+                                    log::info!("looping {}", song.display());
+                                    audio = reopened;
+                                    decoded_this_pass = false;
+                                    silent_frames = 0;
+                                    let _ = tx_events.send(PlaybackEvent::TrackEnded(song.clone()));
+                                    start_track(&decode_shared, &tx_events, &song, &audio);
+                                    continue;
+                                }
+                                Err(e) => log::error!("can't loop {}: {e}", song.display()),
+                            }
+                        }
+
+                        // Flush the last partial chunk and what the filter still holds, or
+                        // the end of the track is lost. The padding is left out, so the
+                        // next track follows without a gap.
+                        if let Some(ref mut resampler) = resampler {
+                            resampler.finish(
+                                &audio_buf,
+                                audio.channels(),
+                                chunk_size,
+                                &mut resampler_final,
+                            );
+                            audio_buf.clear();
+                            let output = fold_to_stereo(
+                                &resampler_final,
+                                audio.channels(),
+                                &decode_shared.downmix.lock().unwrap(),
+                                &mut stereo,
+                            );
+                            crossfade.process(output, &mut faded);
+                            send_output(
+                                &faded,
+                                &mut sink,
+                                &mut analysis_send,
+                                &decode_shared,
+                                latency_ms,
+                            );
+                        }
+                        break;
+                    }
+                }
+            }
+
+            log::info!("Song over");
+            // A stop or skip has already dropped whatever was held.
+            if !stopped && !skipped {
+                if crossfade.fade_len() > 0 && !queue.is_empty() {
+                    crossfade.start_fade();
+                } else {
+                    crossfade.drain(&mut faded);
+                    send_output(
+                        &faded,
+                        &mut sink,
+                        &mut analysis_send,
+                        &decode_shared,
+                        latency_ms,
+                    );
+                }
+            }
+            decode_shared.track_active.store(false, Ordering::Relaxed);
+            let _ = tx_events.send(PlaybackEvent::TrackEnded(song));
+
+            if !stopped
+                && !finish_when_played(&decode_shared, &queue, &rx_stop, &tx_events, latency_ms)
+            {
+                return;
+            }
+        }
+    });
+
+    Decoder {
+        tx_play_song,
+        rx_play_song,
+        tx_seek,
+        tx_stop,
+        rx_events,
+        thread,
+    }
+}
+
+/// Fold interleaved `samples` with `channels` per frame to the rings' stereo by
+/// `downmix`, using `stereo` as scratch space unless they pass through as they are.
+fn fold_to_stereo<'a>(
+    samples: &'a [f32],
+    channels: usize,
+    downmix: &Downmix,
+    stereo: &'a mut Vec<f32>,
+) -> &'a [f32] {
+    if channels == RING_CHANNELS && *downmix == Downmix::Stereo {
+        return samples;
+    }
+
+    stereo.clear();
+    for frame in samples.chunks(channels) {
+        let (left, right) = downmix.apply(frame);
+        stereo.extend([left, right]);
+    }
+    stereo
+}
+
+/// A resampler from `audio`'s rate to `sample_rate`, or `None` if they already match.
+fn new_resampler(
+    audio: &AudioFile,
+    sample_rate: u32,
+    resample_quality: ResampleQuality,
+    chunk_size: usize,
+) -> Result<Option<Resampler>, Time2FreqError> {
+    if audio.sample_rate() == sample_rate {
+        log::info!("no resampler, file matches the output rate");
+        return Ok(None);
+    }
+    let interpolation_params = resample_quality.parameters();
+    let ratio = sample_rate as f64 / audio.sample_rate() as f64;
+    // The filter is centered half its length in, which comes out as that much silence.
+    let skip = (interpolation_params.sinc_len as f64 / 2.0 * ratio) as usize;
+    let inner = rubato::SincFixedIn::<f32>::new(
+        ratio,
+        2.0,
+        interpolation_params,
+        chunk_size,
+        audio.channels(),
+    )?;
+
+    let buf_in = inner.input_buffer_allocate();
+    let buf_out = inner.output_buffer_allocate();
+    log::info!(
+        "buf_in: {} buf_out: {}",
+        buf_in[0].capacity(),
+        buf_out[0].capacity()
+    );
+
+    Ok(Some(Resampler {
+        inner,
+        buf_in,
+        buf_out,
+        ratio,
+        skip,
+        delay: skip,
+        frames_in: 0,
+        frames_out: 0,
+    }))
+}
+
+/// If nothing's queued, report `PlaybackEvent::Finished` once the buffered tail is
+/// audible. A stop, or a song queued in the meantime, ends the wait without it. Returns
+/// `false` on shutdown.
+fn finish_when_played(
+    shared: &Shared,
+    queue: &channel::Receiver<PathBuf>,
+    rx_stop: &channel::Receiver<()>,
+    tx_events: &channel::Sender<PlaybackEvent>,
+    latency_ms: usize,
+) -> bool {
+    while queue.is_empty() {
+        if shared.shutdown.load(Ordering::Relaxed) {
+            return false;
+        }
+        if rx_stop.try_recv().is_ok() {
+            break;
+        }
+        let written = shared.ring_written.load(Ordering::Relaxed);
+        if shared.ring_played.load(Ordering::Relaxed) >= written {
+            let _ = tx_events.send(PlaybackEvent::Finished);
+            break;
+        }
+        thread::sleep(Duration::from_millis(latency_ms as u64 / 2));
+    }
+    true
+}
+
+/// Mark where `audio` begins in the ring and announce it. The track becomes audible once
+/// what's already buffered has played.
+fn start_track(
+    shared: &Shared,
+    tx_events: &channel::Sender<PlaybackEvent>,
+    song: &Path,
+    audio: &AudioFile,
+) {
+    let written = shared.ring_written.load(Ordering::Relaxed);
+    shared.track_start.store(written as i64, Ordering::Relaxed);
+    shared.duration.store(audio.duration());
+    shared.track_active.store(true, Ordering::Relaxed);
+    shared.scrubbing.store(false, Ordering::Relaxed);
+    let _ = tx_events.send(PlaybackEvent::TrackStarted(song.to_path_buf()));
+    let _ = tx_events.send(PlaybackEvent::TrackInfo(
+        song.to_path_buf(),
+        audio.tags().clone(),
+    ));
+}
+
+/// Send interleaved stereo to the sink and the analysis ring buffer, waiting for room in
+/// the sink. Returns `false` if the track should stop, on shutdown or a sink error.
+fn send_output(
+    output: &[f32],
+    sink: &mut impl OutputSink,
+    analysis_send: &mut rtrb::Producer<f32>,
+    shared: &Shared,
+    latency_ms: usize,
+) -> bool {
+    let lossless = shared.lossless_analysis.load(Ordering::Relaxed);
+    for frame in output.chunks(RING_CHANNELS) {
+        let full = |sink_space: usize, analysis_space: usize| {
+            sink_space < frame.len() || (lossless && analysis_space < frame.len())
+        };
+        if full(sink.space(), analysis_send.slots()) {
+            let started = Instant::now();
+            while full(sink.space(), analysis_send.slots()) {
+                if shared.shutdown.load(Ordering::Relaxed) {
+                    return false;
+                }
+                sink.wait(Duration::from_millis(latency_ms as u64 / 2));
+            }
+            log::debug!("waited {:?} for room in the sink", started.elapsed());
+        }
+        if let Err(e) = sink.write(frame) {
+            log::error!("{e}");
+            return false;
+        }
+        for sample in frame {
+            if analysis_send.push(*sample).is_err() {
+                shared.analysis_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    shared
+        .ring_written
+        .fetch_add(output.len() as u64, Ordering::Relaxed);
+    if !sink.buffers() {
+        shared
+            .ring_played
+            .fetch_add(output.len() as u64, Ordering::Relaxed);
+    }
+
+    true
+}
+
+/// Check buffering options up front, where a bad value would otherwise panic deep in
+/// the ring buffer or resampler. Returns the latency in samples.
+fn check_buffering(
+    latency_ms: usize,
+    sample_rate: u32,
+    channels: usize,
+    chunk_size: usize,
+    buffer_multiplier: usize,
+) -> Result<usize, Time2FreqError> {
+    let invalid = |message: String| Err(Time2FreqError::InvalidConfig(message));
+    if chunk_size == 0 {
+        return invalid("chunk_size must be > 0".into());
+    }
+    if buffer_multiplier < 1 {
+        return invalid(format!(
+            "buffer_multiplier must be at least 1, got {buffer_multiplier}"
+        ));
+    }
+
+    let latency_frames = (latency_ms as f32 * sample_rate as f32 / 1000.0).round() as usize;
+    if latency_frames == 0 {
+        return invalid(format!(
+            "latency_ms too small for sample rate: {latency_ms} ms is under one frame at \
+             {sample_rate} Hz"
+        ));
+    }
+
+    let latency_samples = latency_frames * channels;
+    if latency_samples * buffer_multiplier < chunk_size * channels {
+        return invalid(format!(
+            "chunk_size {chunk_size} doesn't fit in the {} ms buffer; raise latency_ms or \
+             buffer_multiplier, or lower chunk_size",
+            latency_ms * buffer_multiplier
+        ));
+    }
+
+    Ok(latency_samples)
+}
+
+impl Drop for AudioPlayer {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+
+        if let Some(Err(e)) = self.stream.as_ref().map(StreamTrait::pause) {
+            log::warn!("couldn't pause output stream: {e}");
+        }
+
+        if let Some(decoder) = self.decoder.take() {
+            let Decoder {
+                tx_play_song,
+                tx_seek,
+                thread,
+                ..
+            } = decoder;
+
+            // Disconnecting the channels wakes an idle decode thread so it can exit.
+            drop((tx_play_song, tx_seek));
+
+            if thread.join().is_err() {
+                log::error!("decode thread panicked");
+            }
+        }
     }
 }
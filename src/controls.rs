@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use winit::event::VirtualKeyCode;
+
+/// Volume change per press of the volume keys.
+const VOLUME_STEP: f32 = 0.1;
+
+/// Jump per press of the seek keys.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Something a key asks the player or visualizer to do, for the event loop to carry out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    TogglePause,
+    /// Change the volume by this much.
+    Volume(f32),
+    SeekForward(Duration),
+    SeekBack(Duration),
+    NextTrack,
+    Stop,
+    /// Move on to the next FFT window function.
+    CycleWindow,
+    ToggleStats,
+    ToggleOverlay,
+    /// Hold the scene on the current analysis, or let it go.
+    ToggleFreeze,
+    /// Save the scene as a PNG.
+    Screenshot,
+}
+
+/// Keymap from key presses to `Command`s.
+///
+/// The default binds Escape to quit, space to pause, up and down to volume, left and
+/// right to seek, N to skip, S to stop, W to cycle the window function, F to show frame
+/// stats, Z to freeze the scene, F1 to hide the overlay, and F12 to save a screenshot.
+#[derive(Debug, Clone)]
+pub struct Controls {
+    bindings: Vec<(VirtualKeyCode, Command)>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (VirtualKeyCode::Escape, Command::Quit),
+                (VirtualKeyCode::Space, Command::TogglePause),
+                (VirtualKeyCode::Up, Command::Volume(VOLUME_STEP)),
+                (VirtualKeyCode::Down, Command::Volume(-VOLUME_STEP)),
+                (VirtualKeyCode::Right, Command::SeekForward(SEEK_STEP)),
+                (VirtualKeyCode::Left, Command::SeekBack(SEEK_STEP)),
+                (VirtualKeyCode::N, Command::NextTrack),
+                (VirtualKeyCode::S, Command::Stop),
+                (VirtualKeyCode::W, Command::CycleWindow),
+                (VirtualKeyCode::F, Command::ToggleStats),
+                (VirtualKeyCode::Z, Command::ToggleFreeze),
+                (VirtualKeyCode::F1, Command::ToggleOverlay),
+                (VirtualKeyCode::F12, Command::Screenshot),
+            ],
+        }
+    }
+}
+
+impl Controls {
+    /// A keymap with nothing bound.
+    pub fn empty() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Make `key` send `command`, replacing whatever it sent before.
+    pub fn bind(&mut self, key: VirtualKeyCode, command: Command) {
+        self.unbind(key);
+        self.bindings.push((key, command));
+    }
+
+    pub fn unbind(&mut self, key: VirtualKeyCode) {
+        self.bindings.retain(|(bound, _)| *bound != key);
+    }
+
+    /// The command for a press of `keycode`, if it's bound.
+    pub fn handle(&self, keycode: VirtualKeyCode) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|(key, _)| *key == keycode)
+            .map(|(_, command)| *command)
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::VecDeque, f32::consts::FRAC_PI_2};
+
+/// Blends the end of one track into the start of the next.
+///
+/// Output is held back by the fade length, so when a track ends its last `len` samples
+/// are still on hand to mix under the next track's head, rather than having to open the
+/// next file early. Gains follow an equal-power curve so uncorrelated material keeps its
+/// loudness through the fade. Works on interleaved samples of any channel count, as long
+/// as `len` is a whole number of frames.
+#[derive(Debug, Clone, Default)]
+pub struct Crossfade {
+    len: usize,
+    held: VecDeque<f32>,
+    tail: Vec<f32>,
+    /// Samples of `tail` already mixed in.
+    faded: usize,
+}
+
+impl Crossfade {
+    /// Fade over `len` samples; zero plays tracks back to back.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            ..Self::default()
+        }
+    }
+
+    /// Change the fade length. Output already held is released as it's pushed out.
+    pub fn set_fade_len(&mut self, len: usize) {
+        self.len = len;
+    }
+
+    pub fn fade_len(&self) -> usize {
+        self.len
+    }
+
+    /// Take the next `input`, mixing it over any fading tail, and replace `output` with
+    /// whatever is no longer held back.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        // Nothing to hold or mix, the usual case with crossfading off.
+        if self.len == 0 && self.held.is_empty() && self.faded == self.tail.len() {
+            output.extend_from_slice(input);
+            return;
+        }
+        for sample in input {
+            let mut sample = *sample;
+            if let Some(tail) = self.tail.get(self.faded) {
+                let t = self.faded as f32 / self.tail.len() as f32;
+                let angle = t * FRAC_PI_2;
+                sample = sample * angle.sin() + tail * angle.cos();
+                self.faded += 1;
+            }
+            self.held.push_back(sample);
+        }
+        while self.held.len() > self.len {
+            output.extend(self.held.pop_front());
+        }
+    }
+
+    /// The current track ended with another to follow: fade out what's held under the
+    /// start of the next one.
+    pub fn start_fade(&mut self) {
+        let rest = self.rest_of_tail();
+        self.held.extend(rest);
+        self.tail = self.held.drain(..).collect();
+    }
+
+    /// Replace `output` with everything held, followed by the rest of any tail that ran
+    /// out of track to fade into, e.g. at the end of the queue.
+    pub fn drain(&mut self, output: &mut Vec<f32>) {
+        output.clear();
+        output.extend(self.held.drain(..));
+        output.extend(self.rest_of_tail());
+    }
+
+    /// Drop everything held, as for a seek or skip.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.tail.clear();
+        self.faded = 0;
+    }
+
+    /// Take the unmixed rest of the tail, still fading out.
+    fn rest_of_tail(&mut self) -> Vec<f32> {
+        let len = self.tail.len() as f32;
+        let rest = self
+            .tail
+            .iter()
+            .enumerate()
+            .skip(self.faded)
+            .map(|(i, tail)| tail * (i as f32 / len * FRAC_PI_2).cos())
+            .collect();
+        self.tail.clear();
+        self.faded = 0;
+        rest
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use symphonia::core::errors::Error as SymphoniaError;
+
+/// Why opening, decoding, or playing audio failed, for callers to tell the cases apart.
+#[derive(Debug, thiserror::Error)]
+pub enum Time2FreqError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("can't fetch stream: {0}")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("unsupported format: {0}")]
+    UnsupportedFormat(#[source] SymphoniaError),
+    #[error("no default track")]
+    NoDefaultTrack,
+    #[error("unsupported codec: {0}")]
+    UnsupportedCodec(#[source] SymphoniaError),
+    /// The decoder doesn't say the sample rate or channel layout up front.
+    #[error("unknown sample rate or channel layout")]
+    UnknownLayout,
+    #[error("decode error: {0}")]
+    DecodeFailed(#[source] SymphoniaError),
+    #[error("can't seek to {position:?}: {source}")]
+    SeekFailed {
+        position: Duration,
+        source: SymphoniaError,
+    },
+    /// The audio device went away or can't run the stream asked of it.
+    #[error("audio device unavailable: {0}")]
+    DeviceUnavailable(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// No resampler fits the file's sample rate to the output's.
+    #[error("can't resample: {0}")]
+    ResampleFailed(#[from] rubato::ResamplerConstructionError),
+    /// Writing to a sink other than the device, e.g. a WAV file, failed.
+    #[error("can't write output: {0}")]
+    OutputFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Buffering options that can't work together, caught before anything starts.
+    #[error("{0}")]
+    InvalidConfig(String),
+}
+
+impl From<cpal::BuildStreamError> for Time2FreqError {
+    fn from(e: cpal::BuildStreamError) -> Self {
+        Self::DeviceUnavailable(Box::new(e))
+    }
+}
+
+impl From<cpal::PlayStreamError> for Time2FreqError {
+    fn from(e: cpal::PlayStreamError) -> Self {
+        Self::DeviceUnavailable(Box::new(e))
+    }
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Time2FreqError>;
@@ -1,8 +1,82 @@
-use crate::wgpu;
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{wgpu, Palette, PALETTE_PRESETS};
+
+/// How long a notice stays on screen.
+const NOTICE_DURATION: Duration = Duration::from_secs(2);
+
+/// How long the dropped-samples warning stays lit after the last drop.
+const DROP_WARNING_DURATION: Duration = Duration::from_secs(1);
+
+/// Extensions offered by the open dialog, the formats symphonia is built with.
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "flac", "mp3", "ogg", "m4a", "mp4", "aac"];
+
+/// Frames of history in the frame-time graph, two seconds at 60 fps.
+const FRAME_HISTORY: usize = 120;
+
+/// Frame time at the top of the graph unless a slower frame stretches it.
+const FRAME_GRAPH_SCALE: Duration = Duration::from_millis(33);
 
-#[derive(Default)]
 struct GuiState {
     repaint: bool,
+    visual_gain: f32,
+    stereo_mix: f32,
+    palette: Palette,
+    /// Index into `PALETTE_PRESETS` of the preset last picked.
+    palette_preset: usize,
+    background: [f32; 3],
+    volume: f32,
+    notice: Option<(String, Instant)>,
+    now_playing: Option<String>,
+    drop_count: usize,
+    last_drop: Option<Instant>,
+    position: Duration,
+    duration: Option<Duration>,
+    seek: Option<Duration>,
+    open: Option<PathBuf>,
+    preset: Option<PathBuf>,
+    scrubbing: bool,
+    show_stats: bool,
+    hidden: bool,
+    frame_times: VecDeque<Duration>,
+}
+
+impl Default for GuiState {
+    fn default() -> Self {
+        Self {
+            repaint: false,
+            visual_gain: 1.0,
+            stereo_mix: 1.0,
+            palette: PALETTE_PRESETS[0].1,
+            palette_preset: 0,
+            background: [0.0; 3],
+            volume: 1.0,
+            notice: None,
+            now_playing: None,
+            drop_count: 0,
+            last_drop: None,
+            position: Duration::ZERO,
+            duration: None,
+            seek: None,
+            open: None,
+            preset: None,
+            scrubbing: false,
+            show_stats: false,
+            hidden: false,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
+    }
+}
+
+/// A tessellated GUI frame, ready to upload and paint.
+pub struct PreparedFrame {
+    clipped_primitives: Vec<egui::epaint::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    screen_descriptor: egui_wgpu::renderer::ScreenDescriptor,
 }
 
 pub struct Gui {
@@ -27,72 +101,344 @@ impl Gui {
     }
 
     pub fn process_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        if self.state.hidden {
+            return false;
+        }
         let response = self.window_state.on_event(&self.context, event);
         self.state.repaint = response.repaint;
 
         response.consumed
     }
 
-    pub fn render(
+    /// Gain the user picked for the visualizer, independent of playback volume.
+    pub fn visual_gain(&self) -> f32 {
+        self.state.visual_gain
+    }
+
+    pub fn set_visual_gain(&mut self, gain: f32) {
+        self.state.visual_gain = gain.max(0.0);
+    }
+
+    /// Stereo width the user picked for the meters, 0 for mono.
+    pub fn stereo_mix(&self) -> f32 {
+        self.state.stereo_mix
+    }
+
+    pub fn set_stereo_mix(&mut self, mix: f32) {
+        self.state.stereo_mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Colors the user picked, from a preset or the color buttons.
+    pub fn palette(&self) -> Palette {
+        self.state.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.state.palette = palette;
+    }
+
+    /// Playback volume the user picked.
+    pub fn volume(&self) -> f32 {
+        self.state.volume
+    }
+
+    /// Move the volume slider, e.g. from a key press.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.state.volume = volume.clamp(0.0, crate::audio::MAX_VOLUME);
+    }
+
+    /// Color behind the scene the user picked, from a preset.
+    pub fn background(&self) -> [f32; 3] {
+        self.state.background
+    }
+
+    pub fn set_background(&mut self, background: [f32; 3]) {
+        self.state.background = background;
+    }
+
+    /// Briefly show a line of text in the overlay.
+    pub fn show_notice(&mut self, text: impl Into<String>) {
+        self.state.notice = Some((text.into(), Instant::now()));
+    }
+
+    /// Name the current track in the overlay.
+    pub fn set_now_playing(&mut self, text: impl Into<String>) {
+        self.state.now_playing = Some(text.into());
+    }
+
+    /// Feed the running total of dropped or underrun samples, lighting a warning whenever
+    /// it grows.
+    pub fn track_drops(&mut self, total: usize) {
+        if total > self.state.drop_count {
+            self.state.last_drop = Some(Instant::now());
+        }
+        self.state.drop_count = total;
+    }
+
+    /// Show playback progress on the seek slider.
+    pub fn set_progress(&mut self, position: Duration, duration: Option<Duration>) {
+        self.state.position = position;
+        self.state.duration = duration;
+    }
+
+    /// Position the user picked on the seek slider since the last call.
+    pub fn take_seek(&mut self) -> Option<Duration> {
+        self.state.seek.take()
+    }
+
+    /// File the user picked with the open button since the last call.
+    pub fn take_open(&mut self) -> Option<PathBuf> {
+        self.state.open.take()
+    }
+
+    /// Preset the user picked with the preset button since the last call.
+    pub fn take_preset(&mut self) -> Option<PathBuf> {
+        self.state.preset.take()
+    }
+
+    /// Whether the seek slider is being dragged.
+    pub fn scrubbing(&self) -> bool {
+        self.state.scrubbing
+    }
+
+    /// Feed the time since the last frame to the FPS counter and frame-time graph.
+    pub fn record_frame_time(&mut self, dt: Duration) {
+        if self.state.frame_times.len() == FRAME_HISTORY {
+            self.state.frame_times.pop_front();
+        }
+        self.state.frame_times.push_back(dt);
+    }
+
+    /// Show or hide the FPS counter and frame-time graph.
+    pub fn toggle_stats(&mut self) {
+        self.state.show_stats = !self.state.show_stats;
+    }
+
+    /// Show or hide the whole overlay. While hidden `render` draws nothing and events
+    /// pass through to the window.
+    pub fn toggle_overlay(&mut self) {
+        self.state.hidden = !self.state.hidden;
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.state.hidden
+    }
+
+    /// Run the UI for this frame and tessellate it, without touching the GPU.
+    pub fn update(
         &mut self,
         window: &winit::window::Window,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        encoder: &mut wgpu::CommandEncoder,
         config: &wgpu::SurfaceConfiguration,
-        view: &wgpu::TextureView,
-    ) {
+    ) -> PreparedFrame {
         let input = self.window_state.take_egui_input(window);
+
+        if let Some((_, shown_at)) = self.state.notice {
+            if shown_at.elapsed() > NOTICE_DURATION {
+                self.state.notice = None;
+            }
+        }
+
         let output = self.context.run(input, |ctx| {
             egui::Area::new("testitout").show(ctx, |ui| {
-                ui.label("Hup Hup Hup");
+                ui.horizontal(|ui| {
+                    if ui.button("Open…").clicked() {
+                        self.state.open = rfd::FileDialog::new()
+                            .add_filter("audio", AUDIO_EXTENSIONS)
+                            .pick_file();
+                    }
+                    if ui.button("Preset…").clicked() {
+                        self.state.preset = rfd::FileDialog::new()
+                            .add_filter("preset", &["toml"])
+                            .pick_file();
+                    }
+                    ui.label(self.state.now_playing.as_deref().unwrap_or("Hup Hup Hup"));
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.state.volume, 0.0..=crate::audio::MAX_VOLUME)
+                        .text("volume"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.state.visual_gain, 0.0..=4.0).text("visual gain"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.state.stereo_mix, 0.0..=1.0).text("stereo width"),
+                );
+                ui.horizontal(|ui| {
+                    let state = &mut self.state;
+                    egui::ComboBox::from_id_source("palette")
+                        .selected_text(PALETTE_PRESETS[state.palette_preset].0)
+                        .show_ui(ui, |ui| {
+                            for (i, (name, palette)) in PALETTE_PRESETS.iter().enumerate() {
+                                if ui
+                                    .selectable_value(&mut state.palette_preset, i, *name)
+                                    .clicked()
+                                {
+                                    state.palette = *palette;
+                                }
+                            }
+                        });
+                    for stop in &mut state.palette {
+                        ui.color_edit_button_rgb(stop);
+                    }
+                    ui.label("background");
+                    ui.color_edit_button_rgb(&mut state.background);
+                });
+                if let Some(duration) = self.state.duration {
+                    let mut secs = self.state.position.as_secs_f32();
+                    let response = ui.add(
+                        egui::Slider::new(&mut secs, 0.0..=duration.as_secs_f32())
+                            .show_value(false)
+                            .text(format!(
+                                "{} / {}",
+                                format_time(self.state.position),
+                                format_time(duration)
+                            )),
+                    );
+                    if response.changed() {
+                        self.state.seek = Some(Duration::from_secs_f32(secs));
+                    }
+                    self.state.scrubbing = response.dragged();
+                } else {
+                    // No slider to drag, so a drag under way when the track ended is over.
+                    self.state.scrubbing = false;
+                }
+                if let Some((notice, _)) = &self.state.notice {
+                    ui.label(notice);
+                }
+                if let Some(last_drop) = self.state.last_drop {
+                    if last_drop.elapsed() < DROP_WARNING_DURATION {
+                        ui.label(
+                            egui::RichText::new("● dropping samples").color(egui::Color32::RED),
+                        );
+                    }
+                }
+                if self.state.show_stats {
+                    frame_stats(ui, &self.state.frame_times);
+                }
             });
         });
 
-        let clipped_primitives: Vec<egui::epaint::ClippedPrimitive> =
-            self.context.tessellate(output.shapes);
+        PreparedFrame {
+            clipped_primitives: self.context.tessellate(output.shapes),
+            textures_delta: output.textures_delta,
+            screen_descriptor: egui_wgpu::renderer::ScreenDescriptor {
+                size_in_pixels: [config.width, config.height],
+                pixels_per_point: 2.0, //self.scale_factor,
+            },
+        }
+    }
 
-        for (id, image_delta) in &output.textures_delta.set {
+    /// Upload a prepared frame's textures and buffers. Must run before `paint`.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &PreparedFrame,
+    ) {
+        for (id, image_delta) in &frame.textures_delta.set {
             self.renderer
                 .update_texture(device, queue, *id, image_delta);
         }
 
-        for id in &output.textures_delta.free {
+        for id in &frame.textures_delta.free {
             self.renderer.free_texture(id);
         }
 
-        let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
-            size_in_pixels: [config.width, config.height],
-            pixels_per_point: 2.0, //self.scale_factor,
-        };
-
         self.renderer.update_buffers(
             device,
             queue,
             encoder,
-            clipped_primitives.as_slice(),
-            &screen_descriptor,
+            frame.clipped_primitives.as_slice(),
+            &frame.screen_descriptor,
         );
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+    /// Draw an uploaded frame into any render pass.
+    pub fn paint<'rp>(
+        &'rp self,
+        frame: &'rp PreparedFrame,
+        render_pass: &mut wgpu::RenderPass<'rp>,
+    ) {
+        self.renderer.render(
+            render_pass,
+            frame.clipped_primitives.as_slice(),
+            &frame.screen_descriptor,
+        );
+    }
 
-            self.renderer.render(
-                &mut render_pass,
-                clipped_primitives.as_slice(),
-                &screen_descriptor,
-            );
+    /// Update, upload, and paint the GUI over `view` in one go, unless it's hidden.
+    pub fn render(
+        &mut self,
+        window: &winit::window::Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        config: &wgpu::SurfaceConfiguration,
+        view: &wgpu::TextureView,
+    ) {
+        if self.state.hidden {
+            // The slider isn't drawn, so it can't still be dragged.
+            self.state.scrubbing = false;
+            return;
         }
+
+        let frame = self.update(window, config);
+        self.upload(device, queue, encoder, &frame);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        self.paint(&frame, &mut render_pass);
+    }
+}
+
+/// FPS averaged over `frame_times`, above a graph of each frame's time.
+fn frame_stats(ui: &mut egui::Ui, frame_times: &VecDeque<Duration>) {
+    let total: Duration = frame_times.iter().sum();
+    if total.is_zero() {
+        return;
     }
+    let fps = frame_times.len() as f32 / total.as_secs_f32();
+    let worst = frame_times.iter().max().copied().unwrap_or_default();
+    ui.label(format!(
+        "{fps:.0} fps, worst {:.1} ms",
+        worst.as_secs_f32() * 1000.0
+    ));
+
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(FRAME_HISTORY as f32, 32.0), egui::Sense::hover());
+    let scale = worst.max(FRAME_GRAPH_SCALE).as_secs_f32();
+    let points = frame_times
+        .iter()
+        .enumerate()
+        .map(|(i, dt)| {
+            egui::pos2(
+                rect.left() + i as f32,
+                rect.bottom() - rect.height() * dt.as_secs_f32() / scale,
+            )
+        })
+        .collect();
+
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(128));
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+    ));
+}
+
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
 }
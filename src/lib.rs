@@ -1,10 +1,41 @@
+mod analyzer;
 pub mod audio;
+mod controls;
+mod crossfade;
+mod error;
 pub mod gui;
+pub mod midi;
+mod offscreen;
+mod onset;
+pub mod osc;
+mod palette;
+mod params;
+mod preset;
 mod resources;
+mod settings;
+pub mod sink;
+mod smoothing;
+mod spectrogram;
+mod spectrum;
 mod uniform;
 mod viewport;
+mod weighting;
 
-pub use uniform::Uniform;
-pub use viewport::Viewport;
+pub use analyzer::Analyzer;
+pub use controls::{Command, Controls};
+pub use crossfade::Crossfade;
+pub use error::Time2FreqError;
+pub use onset::OnsetDetector;
+pub use palette::{Palette, PALETTE_PRESETS};
+pub use params::VizParam;
+pub use preset::Preset;
+pub use resources::{AudioFile, CopyMethod, Tags};
+pub use settings::Settings;
+pub use smoothing::{AttackRelease, PeakHold};
+pub use spectrogram::Spectrogram;
+pub use spectrum::{log_buckets, split_bands, MagnitudeScale, SmoothingMode, Spectrum, WindowFn};
+pub use uniform::{Uniform, UniformRaw, SPECTRUM_SIZE};
+pub use viewport::{GpuOptions, Viewport, DEFAULT_SHADER};
+pub use weighting::AWeighting;
 
 pub use egui_wgpu::wgpu;
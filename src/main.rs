@@ -1,24 +1,666 @@
-use std::{path::PathBuf, time::Instant};
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use clap::Parser;
 use cpal::traits::{DeviceTrait, HostTrait};
+use crossbeam::channel::{Receiver, Sender};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use pollster::block_on;
 use winit::{
-    event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    dpi::PhysicalSize,
+    event::{DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 //use egui_wgpu::wgpu;
 
-use time2freq::{audio::AudioPlayer, gui::Gui, Viewport};
+use time2freq::{
+    audio::{AudioPlayer, Downmix, PlaybackEvent, ResampleQuality},
+    gui::Gui,
+    midi::{self, MidiMapping},
+    osc, wgpu, Command, Controls, GpuOptions, Preset, Settings, Tags, Viewport, VizParam,
+    DEFAULT_SHADER, PALETTE_PRESETS, SPECTRUM_SIZE,
+};
+
+/// Sample rate of files written with `--render-to`, of `--headless` analysis, and of
+/// `--export-frames`, unless `--sample-rate` says otherwise.
+const RENDER_SAMPLE_RATE: u32 = 48_000;
+
+/// Analysis frames per second of audio in `--headless` mode.
+const HEADLESS_FPS: u32 = 60;
 
 #[derive(Parser)]
 struct Cli {
-    #[arg(short, long, default_value_t = 100)]
-    latency_ms: usize,
+    /// Output buffering [default: 100, or the saved value].
+    #[arg(short, long)]
+    latency_ms: Option<usize>,
     #[arg(short, long, default_value_t = 4096)]
     chunk_size: usize,
-    song: PathBuf,
+    /// Resampling quality for files at another sample rate: fast, balanced, or high.
+    #[arg(long, default_value = "balanced")]
+    resample_quality: ResampleQuality,
+    /// Ring buffer size as a multiple of the latency, for headroom under load.
+    #[arg(long, default_value_t = 2)]
+    buffer_multiplier: usize,
+    /// Fraction of the window resolution to render the shader at (0.25 to 1.0).
+    #[arg(long, default_value_t = 1.0)]
+    render_scale: f32,
+    /// How frames are presented: fifo (vsync), mailbox, or immediate. Falls back to fifo
+    /// if the display doesn't support the mode.
+    #[arg(long, value_name = "MODE", default_value = "fifo", value_parser = parse_present_mode)]
+    present_mode: wgpu::PresentMode,
+    /// Background behind the scene as hex RRGGBB, e.g. `1a1a2e` [default: black].
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color)]
+    background: Option<[f32; 3]>,
+    /// Draw to a linear surface instead of an sRGB one, for shaders that gamma-encode
+    /// their own output.
+    #[arg(long)]
+    linear_surface: bool,
+    /// Draw with this graphics API: vulkan, metal, dx12, or gl [default: any].
+    #[arg(long, value_parser = parse_backend)]
+    backend: Option<wgpu::Backends>,
+    /// Favor a low-power (integrated) or high-performance (discrete) GPU: low or high.
+    #[arg(long, value_parser = parse_power_preference)]
+    power_preference: Option<wgpu::PowerPreference>,
+    /// Span of audio the level meters average over, independent of the frame rate.
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    analysis_window_ms: u64,
+    /// Samples per spectrum, a power of two. Larger sizes resolve finer frequencies but
+    /// react more slowly.
+    #[arg(long, value_name = "SAMPLES", default_value_t = 2048, value_parser = parse_fft_size)]
+    fft_size: usize,
+    /// A-weight the signal the level meters measure, so they follow how loud it sounds.
+    #[arg(long)]
+    a_weighting: bool,
+    /// Fold files to stereo as stereo (averaging wider layouts), mono, or itu (ITU-R
+    /// BS.775 for 5.1).
+    #[arg(long, default_value = "stereo")]
+    downmix: Downmix,
+    /// Attack and release of the loudness-driven background [default: 5,250]. `0,0` turns
+    /// smoothing off.
+    #[arg(long, value_name = "ATTACK_MS,RELEASE_MS", value_parser = parse_attack_release)]
+    loudness_smoothing: Option<(Duration, Duration)>,
+    /// Let the scene go still in silence instead of idling on slow noise.
+    #[arg(long)]
+    no_idle_noise: bool,
+    /// Stop the shader's clock too when freezing the scene with Z, for a still frame.
+    #[arg(long)]
+    freeze_time: bool,
+    /// Change visualizer parameters while running from `name value` lines on stdin, e.g.
+    /// `stereo_mix 0.5` or `palette/0/r 1`.
+    #[arg(long)]
+    stdin_params: bool,
+    /// Change visualizer parameters from OSC messages on this UDP port, addressed
+    /// `/viz/<name>` with the names `--stdin-params` takes.
+    #[arg(long, value_name = "PORT")]
+    osc_port: Option<u16>,
+    /// Take beats and tempo from the clock of the first MIDI input with this in its name,
+    /// and control changes as mapped by `--midi-cc`. Waits for the input if it's missing.
+    #[arg(long, value_name = "NAME")]
+    midi_port: Option<String>,
+    /// Have a MIDI controller number set a parameter by a `--stdin-params` name, e.g.
+    /// `1=stereo_mix`, scaled to 0..1. Repeat for more.
+    #[arg(long, value_name = "CC=NAME", value_parser = parse_midi_cc)]
+    midi_cc: Vec<(u8, String)>,
+    /// Visualize a capture device instead of a file, by name or the default if omitted.
+    #[arg(long, value_name = "DEVICE", num_args = 0..=1, default_missing_value = "")]
+    input: Option<String>,
+    /// Visualize the system's audio output (WASAPI, or a PulseAudio/PipeWire monitor).
+    #[arg(long, conflicts_with = "input")]
+    loopback: bool,
+    /// Run the output at this rate instead of the device's default, failing if it's not
+    /// supported. Also sets the rate for `--render-to` and `--headless`.
+    #[arg(long, value_name = "HZ")]
+    sample_rate: Option<u32>,
+    /// Play through the named output device instead of the default or the saved one.
+    #[arg(long, value_name = "NAME")]
+    device: Option<String>,
+    /// Write the decoded audio to a WAV file instead of playing it.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "loopback", "device"])]
+    render_to: Option<PathBuf>,
+    /// Draw the scene with this WGSL file instead of the built-in shader.
+    #[arg(long, value_name = "FILE")]
+    shader: Option<PathBuf>,
+    /// Load the scene shader from this WGSL file and reload it whenever it changes.
+    #[arg(long, value_name = "FILE")]
+    watch_shader: Option<PathBuf>,
+    /// Load a look from this TOML preset: shader, palette, smoothing, and crossovers.
+    /// Flags for the same settings win over it.
+    #[arg(long, value_name = "FILE")]
+    preset: Option<PathBuf>,
+    /// Fade each track into the next over this long.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    crossfade_ms: u64,
+    /// Replay each track when it ends.
+    #[arg(long = "loop")]
+    loop_playback: bool,
+    /// Load the first track without playing it; press space to start.
+    #[arg(long)]
+    paused: bool,
+    /// Run the shader's `time` on the analyzed audio instead of the wall clock, so the
+    /// same files always give the same visuals. It stands still while paused.
+    #[arg(long)]
+    audio_clock: bool,
+    /// Skip the window and audio device, printing each frame's analysis as a line of JSON.
+    #[arg(long, conflicts_with_all = ["input", "loopback", "device", "render_to"])]
+    headless: bool,
+    /// Render the songs' visualization to numbered PNGs in this directory instead of
+    /// playing them, at `--fps` frames per second of audio.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["input", "loopback", "device", "render_to", "headless"]
+    )]
+    export_frames: Option<PathBuf>,
+    /// Frame rate of `--export-frames`.
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: u32,
+    /// Print the available output devices and exit.
+    #[arg(long)]
+    list_devices: bool,
+    /// Files to play in order, or `http://` and `https://` URLs to stream.
+    #[arg(required_unless_present_any = ["input", "loopback", "list_devices"])]
+    songs: Vec<PathBuf>,
+}
+
+fn parse_attack_release(arg: &str) -> Result<(Duration, Duration), String> {
+    let (attack, release) = arg.split_once(',').ok_or("expected ATTACK_MS,RELEASE_MS")?;
+    let millis = |ms: &str| {
+        ms.trim()
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|e| format!("{ms}: {e}"))
+    };
+    Ok((millis(attack)?, millis(release)?))
+}
+
+fn parse_midi_cc(arg: &str) -> Result<(u8, String), String> {
+    let (cc, name) = arg.split_once('=').ok_or("expected CC=NAME")?;
+    let cc = cc
+        .trim()
+        .parse()
+        .ok()
+        .filter(|cc| *cc < 128)
+        .ok_or_else(|| format!("{cc}: controller numbers go from 0 to 127"))?;
+    VizParam::parse(name, 0.0)?;
+    Ok((cc, name.to_string()))
+}
+
+fn parse_fft_size(arg: &str) -> Result<usize, String> {
+    let size: usize = arg.parse().map_err(|e| format!("{arg}: {e}"))?;
+    if size.is_power_of_two() && size >= 2 {
+        Ok(size)
+    } else {
+        Err(format!("{size} isn't a power of two of at least 2"))
+    }
+}
+
+fn parse_color(arg: &str) -> Result<[f32; 3], String> {
+    let hex = arg.strip_prefix('#').unwrap_or(arg);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("{arg}: expected six hex digits, RRGGBB"));
+    }
+    let mut color = [0.0; 3];
+    for (c, digits) in color.iter_mut().zip([&hex[0..2], &hex[2..4], &hex[4..6]]) {
+        let value = u8::from_str_radix(digits, 16).map_err(|e| format!("{arg}: {e}"))?;
+        *c = value as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+fn parse_backend(arg: &str) -> Result<wgpu::Backends, String> {
+    match arg {
+        "vulkan" => Ok(wgpu::Backends::VULKAN),
+        "metal" => Ok(wgpu::Backends::METAL),
+        "dx12" => Ok(wgpu::Backends::DX12),
+        "gl" => Ok(wgpu::Backends::GL),
+        _ => Err(format!("expected vulkan, metal, dx12, or gl, got {arg}")),
+    }
+}
+
+fn parse_power_preference(arg: &str) -> Result<wgpu::PowerPreference, String> {
+    match arg {
+        "low" => Ok(wgpu::PowerPreference::LowPower),
+        "high" => Ok(wgpu::PowerPreference::HighPerformance),
+        _ => Err(format!("expected low or high, got {arg}")),
+    }
+}
+
+fn parse_present_mode(arg: &str) -> Result<wgpu::PresentMode, String> {
+    match arg {
+        "fifo" => Ok(wgpu::PresentMode::Fifo),
+        "mailbox" => Ok(wgpu::PresentMode::Mailbox),
+        "immediate" => Ok(wgpu::PresentMode::Immediate),
+        _ => Err(format!("expected fifo, mailbox, or immediate, got {arg}")),
+    }
+}
+
+/// A PNG in the working directory named for the current time, so screenshots don't
+/// overwrite each other.
+fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("time2freq-{millis}.png"))
+}
+
+fn find_output_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    host.output_devices()
+        .ok()?
+        .find(|device| device.name().map_or(false, |n| n == name))
+}
+
+fn output_device_names(host: &cpal::Host) -> anyhow::Result<Vec<String>> {
+    Ok(host
+        .output_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect())
+}
+
+/// The named output device, or the default if no name is given.
+fn open_output_device(host: &cpal::Host, name: Option<&str>) -> anyhow::Result<cpal::Device> {
+    match name {
+        Some(name) => find_output_device(host, name).ok_or_else(|| {
+            let available = output_device_names(host).unwrap_or_default().join(", ");
+            anyhow::anyhow!("output device not found: {name:?} (available: {available})")
+        }),
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("no default output device")),
+    }
+}
+
+/// The device's default output config, or one at `sample_rate` with the default's format
+/// and channels if possible.
+fn output_config(
+    device: &cpal::Device,
+    sample_rate: Option<u32>,
+) -> anyhow::Result<cpal::SupportedStreamConfig> {
+    let default = device.default_output_config()?;
+    let Some(rate) = sample_rate else {
+        return Ok(default);
+    };
+
+    let ranges: Vec<_> = device.supported_output_configs()?.collect();
+    let supports = |range: &&cpal::SupportedStreamConfigRange| {
+        (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&rate)
+    };
+    let matches_default = |range: &&cpal::SupportedStreamConfigRange| {
+        range.channels() == default.channels() && range.sample_format() == default.sample_format()
+    };
+    ranges
+        .iter()
+        .filter(&supports)
+        .find(matches_default)
+        .or_else(|| ranges.iter().find(&supports))
+        .map(|range| range.clone().with_sample_rate(cpal::SampleRate(rate)))
+        .ok_or_else(|| {
+            let supported = ranges
+                .iter()
+                .map(|range| {
+                    format!(
+                        "{}-{} Hz ({} ch, {})",
+                        range.min_sample_rate().0,
+                        range.max_sample_rate().0,
+                        range.channels(),
+                        range.sample_format()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow::anyhow!("output doesn't support {rate} Hz (supported: {supported})")
+        })
+}
+
+/// Signal on `changes` whenever `path` is written. Watches the parent directory so
+/// editors that save by replacing the file are still seen.
+fn watch_shader(path: &Path) -> anyhow::Result<(RecommendedWatcher, Receiver<()>)> {
+    let (tx, changes) = crossbeam::channel::bounded(1);
+    let name = path.file_name().map(|name| name.to_owned());
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event)
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.iter().any(|p| p.file_name() == name.as_deref()) =>
+            {
+                let _ = tx.try_send(());
+            }
+            Ok(_) => (),
+            Err(e) => log::error!("shader watch: {e}"),
+        })?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    Ok((watcher, changes))
+}
+
+/// Load the preset at `path` and switch `viewport` to its look, leaving the analysis
+/// settings to the caller.
+fn load_preset(path: &Path, viewport: &mut Viewport) -> anyhow::Result<Preset> {
+    let preset = Preset::load(path)?;
+    preset.apply_to_viewport(viewport)?;
+    log::info!("loaded preset {}", path.display());
+    Ok(preset)
+}
+
+fn reload_shader(viewport: &mut Viewport, gui: &mut Gui, path: &Path) {
+    let result = std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|source| viewport.reload_shader(&source));
+    match result {
+        Ok(()) => log::info!("reloaded {}", path.display()),
+        Err(e) => {
+            log::error!("can't reload {}: {e}", path.display());
+            gui.show_notice("shader error, see log");
+        }
+    }
+}
+
+/// Parse `name value` lines from stdin into `params` on a thread of their own, skipping
+/// lines that don't parse.
+fn read_params(params: Sender<VizParam>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("can't read stdin: {e}");
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(param) => {
+                    if params.send(param).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => log::warn!("ignoring parameter: {e}"),
+            }
+        }
+    });
+}
+
+/// Settings the GUI also shows go through it, so the panel stays in step and the next
+/// frame picks them up from there.
+fn apply_param(param: VizParam, gui: &mut Gui, viewport: &mut Viewport) {
+    log::debug!("{param:?}");
+    match param {
+        VizParam::Volume(volume) => gui.set_volume(volume),
+        VizParam::VisualGain(gain) => gui.set_visual_gain(gain),
+        VizParam::StereoMix(mix) => gui.set_stereo_mix(mix),
+        VizParam::Palette(preset) => gui.set_palette(PALETTE_PRESETS[preset].1),
+        VizParam::PaletteColor {
+            stop,
+            channel,
+            value,
+        } => {
+            let mut palette = gui.palette();
+            palette[stop][channel] = value;
+            gui.set_palette(palette);
+        }
+        VizParam::User(index, value) => {
+            let mut user = viewport.uniform.raw.user;
+            user[index] = value;
+            viewport.set_user_data(user);
+        }
+        VizParam::LoudnessAttack(attack) => {
+            let (_, release) = viewport.loudness_smoothing();
+            viewport.set_loudness_smoothing(attack, release);
+        }
+        VizParam::LoudnessRelease(release) => {
+            let (attack, _) = viewport.loudness_smoothing();
+            viewport.set_loudness_smoothing(attack, release);
+        }
+        VizParam::Bpm(bpm) => viewport.set_bpm(bpm),
+        VizParam::Beat => viewport.pulse_beat(),
+    }
+}
+
+/// "Title — Artist" from the tags, or the file name for untagged files.
+fn track_name(path: &Path, tags: &Tags) -> String {
+    match (&tags.title, &tags.artist) {
+        (Some(title), Some(artist)) => format!("{title} — {artist}"),
+        (Some(title), None) => title.clone(),
+        _ => path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Analyze the songs as fast as they decode, frame by frame as if rendering at
+/// `HEADLESS_FPS`, and print one JSON object per frame to stdout.
+fn run_headless(cli: &Cli) {
+    let frame_time = Duration::from_secs(1) / HEADLESS_FPS;
+    let (mut audio, sample_rate) = open_headless(cli).unwrap();
+    let frame_len = (frame_time.as_secs_f64() * sample_rate as f64).round() as usize;
+
+    let mut remaining = cli.songs.len();
+    while wait_for_frame(&mut audio, &mut remaining, frame_len) {
+        let frame = audio.analyze(frame_time);
+        let analyzer = audio.analyzer_mut();
+        let bands = analyzer.bands(frame_time);
+        analyzer.spectrum(frame_time);
+        let spectrum = analyzer.log_spectrum(SPECTRUM_SIZE);
+
+        println!(
+            concat!(
+                "{{\"time\":{},\"rms\":{},\"rms_db\":{},\"peak\":{},",
+                "\"true_peak\":{},\"loudness\":{},\"bands\":{},\"spectrum\":{}}}"
+            ),
+            frame.timestamp.as_secs_f64(),
+            json_array(&frame.rms),
+            json_array(&frame.rms_db),
+            json_array(&frame.peak),
+            json_array(&frame.true_peak),
+            json_number(frame.loudness),
+            json_array(&bands),
+            json_array(&spectrum),
+        );
+    }
+}
+
+/// A player for `--headless` or `--export-frames`, analyzing as set on the command line,
+/// with the songs queued. Comes with the sample rate it analyzes at.
+fn open_headless(cli: &Cli) -> anyhow::Result<(AudioPlayer, u32)> {
+    let sample_rate = cli.sample_rate.unwrap_or(RENDER_SAMPLE_RATE);
+    let mut audio = AudioPlayer::new_headless(sample_rate, cli.chunk_size, cli.resample_quality)?;
+    let analyzer = audio.analyzer_mut();
+    analyzer.set_analysis_window(Duration::from_millis(cli.analysis_window_ms));
+    analyzer.set_fft_size(cli.fft_size)?;
+    analyzer.set_a_weighting(cli.a_weighting);
+    audio.set_downmix(cli.downmix.clone());
+    for song in &cli.songs {
+        audio.enqueue(song.clone());
+    }
+    Ok((audio, sample_rate))
+}
+
+/// Block until a headless `audio` has `frame_len` frames to analyze, counting down the
+/// `remaining` tracks as they end. The tail of the last track is let through short, and
+/// `false` comes back once it's all been analyzed.
+fn wait_for_frame(audio: &mut AudioPlayer, remaining: &mut usize, frame_len: usize) -> bool {
+    loop {
+        while let Some(event) = audio.poll_event() {
+            match event {
+                PlaybackEvent::LoadFailed(path, reason) => {
+                    log::error!("can't play {}: {reason}", path.display());
+                    *remaining -= 1;
+                }
+                PlaybackEvent::TrackEnded(_) => *remaining -= 1,
+                _ => (),
+            }
+        }
+
+        let buffered = audio.buffered_analysis_frames();
+        if *remaining == 0 {
+            return buffered > 0;
+        }
+        if buffered >= frame_len {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Render the songs frame by frame into PNGs in `dir`, stepping the analysis and the
+/// shader's clock by exactly one frame of decoded audio each time, so the output is the
+/// same however long each frame takes to draw.
+fn export_frames(
+    viewport: &mut Viewport,
+    preset: Option<&Preset>,
+    dir: &Path,
+    cli: &Cli,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let frame_time = Duration::from_secs(1) / cli.fps;
+    let (mut audio, sample_rate) = open_headless(cli)?;
+    if let Some(preset) = preset {
+        preset.apply_to_analyzer(audio.analyzer_mut())?;
+    }
+    let frame_len = (frame_time.as_secs_f64() * sample_rate as f64).round() as usize;
+
+    let mut remaining = cli.songs.len();
+    let mut index = 0;
+    while wait_for_frame(&mut audio, &mut remaining, frame_len) {
+        let frame = audio.analyze(frame_time);
+        let analyzer = audio.analyzer_mut();
+        viewport.set_band_energy(analyzer.bands(frame_time));
+        viewport.set_beat(analyzer.onset(frame_time));
+        viewport.set_meters(analyzer.visual_meters());
+        let spectrum = analyzer.spectrum(frame_time).to_vec();
+        viewport.set_spectrum(&analyzer.log_spectrum(SPECTRUM_SIZE));
+        viewport.set_time(Some(frame.timestamp));
+        viewport.update(
+            frame_time,
+            frame.visual_levels(),
+            frame.visual_peak(),
+            &spectrum,
+        );
+
+        let path = dir.join(format!("frame-{index:06}.png"));
+        viewport.capture()?.save(&path)?;
+        index += 1;
+    }
+    log::info!("exported {index} frames to {}", dir.display());
+
+    Ok(())
+}
+
+/// JSON has no infinities or NaN, so those become `null`.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_array(values: &[f32]) -> String {
+    let values: Vec<_> = values.iter().map(|value| json_number(*value)).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Build an analysis-only player on the named capture device, or the default if empty.
+fn open_input(host: &cpal::Host, name: &str, latency_ms: usize) -> anyhow::Result<AudioPlayer> {
+    let device = if name.is_empty() {
+        host.default_input_device()
+    } else {
+        host.input_devices()?
+            .find(|device| device.name().map_or(false, |n| n == name))
+    }
+    .ok_or_else(|| anyhow::anyhow!("input device not found: {name:?}"))?;
+    let config = device.default_input_config()?;
+
+    capture(&device, config, latency_ms)
+}
+
+/// Capture whatever the system is playing.
+///
+/// WASAPI can record an output device directly. Elsewhere this looks for a PulseAudio or
+/// PipeWire "Monitor of ..." source among the inputs, which most Linux desktops provide.
+fn open_loopback(host: &cpal::Host, latency_ms: usize) -> anyhow::Result<AudioPlayer> {
+    let (device, config) = if cfg!(target_os = "windows") {
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("no output device to capture"))?;
+        let config = device.default_output_config()?;
+        (device, config)
+    } else {
+        let device = host
+            .input_devices()?
+            .find(|device| {
+                device
+                    .name()
+                    .map_or(false, |name| name.to_lowercase().contains("monitor"))
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "loopback capture isn't supported by the {} host and no monitor source \
+                     was found; try --input with a monitor device",
+                    host.id().name()
+                )
+            })?;
+        let config = device.default_input_config()?;
+        (device, config)
+    };
+
+    capture(&device, config, latency_ms)
+}
+
+fn capture(
+    device: &cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    latency_ms: usize,
+) -> anyhow::Result<AudioPlayer> {
+    log::info!("capture device: {}", device.name()?);
+
+    let new = match config.sample_format() {
+        cpal::SampleFormat::I16 => AudioPlayer::new_input::<i16>,
+        cpal::SampleFormat::U16 => AudioPlayer::new_input::<u16>,
+        cpal::SampleFormat::F32 => AudioPlayer::new_input::<f32>,
+        format => anyhow::bail!("unsupported input format: {format}"),
+    };
+
+    Ok(new(device, &config.into(), latency_ms)?)
+}
+
+/// Build a player on `device` with a stream of its native sample type.
+fn playback(
+    device: &cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    latency_ms: usize,
+    cli: &Cli,
+) -> anyhow::Result<AudioPlayer> {
+    let new = match config.sample_format() {
+        cpal::SampleFormat::I8 => AudioPlayer::new::<i8>,
+        cpal::SampleFormat::I16 => AudioPlayer::new::<i16>,
+        cpal::SampleFormat::U16 => AudioPlayer::new::<u16>,
+        cpal::SampleFormat::I32 => AudioPlayer::new::<i32>,
+        cpal::SampleFormat::F32 => AudioPlayer::new::<f32>,
+        format => anyhow::bail!("unsupported output format: {format}"),
+    };
+
+    Ok(new(
+        device,
+        &config.into(),
+        latency_ms,
+        cli.chunk_size,
+        cli.resample_quality,
+        cli.buffer_multiplier,
+        cli.paused,
+    )?)
 }
 
 fn main() {
@@ -26,34 +668,178 @@ fn main() {
     let _log = tailog::init();
     log::info!("Starting...");
 
+    if cli.list_devices {
+        let host = cpal::default_host();
+        for name in output_device_names(&host).unwrap() {
+            println!("{name}");
+        }
+        return;
+    }
+
+    if cli.headless {
+        run_headless(&cli);
+        return;
+    }
+
+    let settings_path = Settings::path();
+    let mut settings = settings_path
+        .as_deref()
+        .map(Settings::load)
+        .unwrap_or_default();
+    // Flags on the command line win over the saved values, and are saved in turn.
+    if let Some(latency_ms) = cli.latency_ms {
+        settings.latency_ms = latency_ms;
+    }
+    if cli.device.is_some() {
+        settings.device = cli.device.clone();
+    }
+    if cli.shader.is_some() {
+        settings.shader = cli.shader.clone();
+    }
+
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    // Exporting draws offscreen, so the window only has to exist.
+    let mut window_builder = WindowBuilder::new().with_visible(cli.export_frames.is_none());
+    if let Some((width, height)) = settings.window_size {
+        window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    let window = window_builder.build(&event_loop).unwrap();
     let mut last_render_time = Instant::now();
-    let mut viewport = block_on(Viewport::new(&window));
+    let gpu_options = GpuOptions {
+        backends: cli.backend.unwrap_or_else(wgpu::Backends::all),
+        power_preference: cli.power_preference.unwrap_or_default(),
+        srgb: !cli.linear_surface,
+    };
+    let new_viewport =
+        |source: &str| block_on(Viewport::new_with_options(&window, source, gpu_options));
+    let mut viewport = match settings.shader.clone() {
+        Some(path) if cli.shader.is_some() => {
+            let source = std::fs::read_to_string(path).unwrap();
+            new_viewport(&source).unwrap()
+        }
+        // A saved shader may have moved or broken since; fall back to the built-in one.
+        Some(path) => {
+            let viewport = match std::fs::read_to_string(&path) {
+                Ok(source) => new_viewport(&source),
+                Err(e) => Err(e.into()),
+            };
+            viewport.unwrap_or_else(|e| {
+                log::warn!("can't load saved shader {}: {e}", path.display());
+                settings.shader = None;
+                new_viewport(DEFAULT_SHADER).unwrap()
+            })
+        }
+        None => new_viewport(DEFAULT_SHADER).unwrap(),
+    };
+    let preset = cli.preset.as_deref().and_then(|path| {
+        load_preset(path, &mut viewport)
+            .map_err(|e| log::error!("can't load preset {}: {e}", path.display()))
+            .ok()
+    });
+    let palette = preset
+        .as_ref()
+        .and_then(|preset| preset.palette)
+        .unwrap_or(settings.palette);
+    viewport.set_render_scale(cli.render_scale);
+    viewport.set_present_mode(cli.present_mode);
+    if let Some((attack, release)) = cli.loudness_smoothing {
+        viewport.set_loudness_smoothing(attack, release);
+    }
+    if cli.no_idle_noise {
+        viewport.set_idle_noise(None);
+    }
+    viewport.set_freeze_time(cli.freeze_time);
+
+    if let Some(background) = cli.background {
+        viewport.set_clear_color(background);
+    }
+
+    if let Some(dir) = &cli.export_frames {
+        viewport.set_palette(palette);
+        export_frames(&mut viewport, preset.as_ref(), dir, &cli).unwrap();
+        return;
+    }
 
     let mut gui = Gui::new(&viewport.device, &event_loop, viewport.config.format);
+    gui.set_volume(settings.volume);
+    gui.set_palette(palette);
+    gui.set_background(viewport.clear_color());
 
-    let audio_device = cpal::default_host().default_output_device().unwrap();
-    let audio_config = audio_device.default_output_config().unwrap();
+    // Senders go to each source of runtime parameter changes; with none, nothing arrives.
+    let (params_send, params) = crossbeam::channel::unbounded();
+    if cli.stdin_params {
+        read_params(params_send.clone());
+    }
+    if let Some(port) = cli.osc_port {
+        osc::listen(port, params_send.clone()).unwrap();
+    }
+    if let Some(name) = cli.midi_port.clone() {
+        let mapping = MidiMapping::new(cli.midi_cc.clone());
+        midi::listen(name, mapping, params_send.clone()).unwrap();
+    }
+    drop(params_send);
 
-    let mut audio = match audio_config.sample_format() {
-        cpal::SampleFormat::I8 => AudioPlayer::new::<i8>(
-            &audio_device,
-            &audio_config.into(),
-            cli.latency_ms,
-            cli.chunk_size,
-        ),
-        cpal::SampleFormat::F32 => AudioPlayer::new::<f32>(
-            &audio_device,
-            &audio_config.into(),
-            cli.latency_ms,
+    let shader_watch = cli.watch_shader.map(|path| {
+        let (watcher, changes) = watch_shader(&path).unwrap();
+        reload_shader(&mut viewport, &mut gui, &path);
+        (path, watcher, changes)
+    });
+
+    let host = cpal::default_host();
+
+    let mut audio = if let Some(name) = &cli.input {
+        open_input(&host, name, settings.latency_ms).unwrap()
+    } else if cli.loopback {
+        open_loopback(&host, settings.latency_ms).unwrap()
+    } else if let Some(path) = &cli.render_to {
+        AudioPlayer::new_wav(
+            path,
+            cli.sample_rate.unwrap_or(RENDER_SAMPLE_RATE),
+            settings.latency_ms,
             cli.chunk_size,
-        ),
-        _ => panic!("unsupported format"),
-    }
-    .unwrap();
+            cli.resample_quality,
+        )
+        .unwrap()
+    } else {
+        let audio_device = open_output_device(&host, settings.device.as_deref())
+            .or_else(|e| match cli.device {
+                Some(_) => Err(e),
+                // The saved device may be unplugged.
+                None => {
+                    log::warn!("{e}, using the default");
+                    settings.device = None;
+                    open_output_device(&host, None)
+                }
+            })
+            .unwrap();
+        let audio_config = output_config(&audio_device, cli.sample_rate).unwrap();
+
+        playback(&audio_device, audio_config, settings.latency_ms, &cli).unwrap()
+    };
     //audio.play(&std::env::args().nth(1).expect("Expected song file"));
-    audio.play(cli.song);
+    audio.set_loop(cli.loop_playback);
+    audio.set_crossfade(Duration::from_millis(cli.crossfade_ms));
+    let analyzer = audio.analyzer_mut();
+    analyzer.set_analysis_window(Duration::from_millis(cli.analysis_window_ms));
+    analyzer.set_fft_size(cli.fft_size).unwrap();
+    analyzer.set_a_weighting(cli.a_weighting);
+    if let Some(preset) = &preset {
+        if let Err(e) = preset.apply_to_analyzer(analyzer) {
+            log::error!("can't apply preset analysis: {e}");
+        }
+    }
+    audio.set_downmix(cli.downmix.clone());
+    if cli.paused {
+        // Already the case for a device, which also skips its pre-fill.
+        audio.pause();
+    }
+    for song in cli.songs {
+        audio.enqueue(song);
+    }
+    // Once a track has been dropped or opened, stay up at the end of the queue for more.
+    let mut interactive = false;
+    let controls = Controls::default();
+    let audio_clock = cli.audio_clock;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::DeviceEvent {
@@ -70,16 +856,70 @@ fn main() {
             }
 
             match event {
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+
+                WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
                             state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            virtual_keycode: Some(key),
                             ..
                         },
                     ..
-                } => *control_flow = ControlFlow::Exit,
+                } => match controls.handle(*key) {
+                    Some(Command::Quit) => *control_flow = ControlFlow::Exit,
+                    Some(Command::TogglePause) => {
+                        if audio.is_paused() {
+                            audio.resume();
+                        } else {
+                            audio.pause();
+                        }
+                    }
+                    Some(Command::Volume(step)) => {
+                        gui.set_volume(gui.volume() + step);
+                        gui.show_notice(format!("volume: {:.0}%", gui.volume() * 100.0));
+                    }
+                    Some(Command::SeekForward(step)) => {
+                        let position = audio.position() + step;
+                        audio.seek(audio.duration().map_or(position, |d| position.min(d)));
+                    }
+                    Some(Command::SeekBack(step)) => {
+                        audio.seek(audio.position().saturating_sub(step));
+                    }
+                    Some(Command::NextTrack) => audio.skip(),
+                    Some(Command::Stop) => {
+                        audio.stop();
+                        gui.show_notice("stopped");
+                    }
+                    Some(Command::CycleWindow) => {
+                        let window_fn = audio.analyzer().window().next();
+                        audio.analyzer_mut().set_window(window_fn);
+                        gui.show_notice(format!("window: {}", window_fn.name()));
+                    }
+                    Some(Command::ToggleStats) => gui.toggle_stats(),
+                    Some(Command::ToggleOverlay) => gui.toggle_overlay(),
+                    Some(Command::ToggleFreeze) => {
+                        viewport.set_frozen(!viewport.frozen());
+                        gui.show_notice(if viewport.frozen() { "frozen" } else { "live" });
+                    }
+                    Some(Command::Screenshot) => {
+                        let path = screenshot_path();
+                        match viewport.capture().and_then(|image| Ok(image.save(&path)?)) {
+                            Ok(()) => gui.show_notice(format!("saved {}", path.display())),
+                            Err(e) => {
+                                log::error!("can't save screenshot: {e}");
+                                gui.show_notice(format!("can't save screenshot: {e}"));
+                            }
+                        }
+                    }
+                    None => (),
+                },
+
+                // Replaces whatever is playing, like picking a file with the open button.
+                WindowEvent::DroppedFile(path) => {
+                    audio.play(path.clone());
+                    interactive = true;
+                }
 
                 WindowEvent::Resized(physical_size) => viewport.resize(*physical_size),
 
@@ -91,7 +931,13 @@ fn main() {
                     device_id: _,
                     position,
                     ..
-                } => viewport.uniform.raw.mouse_pos = [position.x as f32, position.y as f32],
+                } => viewport.set_mouse_position(*position),
+
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => viewport.set_mouse_down(*state == ElementState::Pressed),
 
                 _ => (),
             }
@@ -101,26 +947,108 @@ fn main() {
             let now = Instant::now();
             let dt = now - last_render_time;
             last_render_time = now;
+            gui.record_frame_time(dt);
 
-            // Try to scale and normalize the levels for max visual effect.
-            let (mut rms, mut loudness) = audio.rms(dt);
+            if let Some((path, _, changes)) = &shader_watch {
+                if changes.try_recv().is_ok() {
+                    reload_shader(&mut viewport, &mut gui, path);
+                }
+            }
 
-            rms[0] = (1. - 20. * rms[0].log10() / -20.).clamp(-1., 1.);
-            rms[1] = (1. - 20. * rms[1].log10() / -20.).clamp(-1., 1.);
+            for param in params.try_iter() {
+                apply_param(param, &mut gui, &mut viewport);
+            }
+            audio.set_volume(gui.volume());
+            audio.set_visual_gain(gui.visual_gain());
+            viewport.set_stereo_mix(gui.stereo_mix());
+            viewport.set_palette(gui.palette());
+            viewport.set_clear_color(gui.background());
+            if let Some(position) = gui.take_seek() {
+                audio.seek(position);
+            }
+            if let Some(song) = gui.take_open() {
+                audio.play(song);
+                interactive = true;
+            }
+            if let Some(path) = gui.take_preset() {
+                let result = load_preset(&path, &mut viewport).and_then(|preset| {
+                    preset.apply_to_analyzer(audio.analyzer_mut())?;
+                    Ok(preset)
+                });
+                match result {
+                    Ok(preset) => {
+                        if let Some(palette) = preset.palette {
+                            gui.set_palette(palette);
+                        }
+                        gui.set_background(viewport.clear_color());
+                        gui.show_notice(format!("preset: {}", path.display()));
+                    }
+                    Err(e) => {
+                        log::error!("can't load preset {}: {e}", path.display());
+                        gui.show_notice(format!("can't load preset: {e}"));
+                    }
+                }
+            }
+            audio.set_scrubbing(gui.scrubbing());
+            gui.set_progress(audio.position(), audio.duration());
+            gui.track_drops(audio.dropped_samples() + audio.underruns());
+            while let Some(event) = audio.poll_event() {
+                match event {
+                    PlaybackEvent::LoadFailed(path, reason) => {
+                        gui.show_notice(format!("can't play {}: {reason}", path.display()));
+                    }
+                    PlaybackEvent::TrackStarted(_) => (),
+                    PlaybackEvent::TrackInfo(path, tags) => {
+                        gui.set_now_playing(track_name(&path, &tags));
+                    }
+                    PlaybackEvent::TrackEnded(_) => (),
+                    PlaybackEvent::Finished if !interactive => *control_flow = ControlFlow::Exit,
+                    PlaybackEvent::Finished => (),
+                }
+            }
 
-            loudness = (10f32.powf(loudness / 20.) * 20.) * 2. - 1.;
+            let frame = audio.analyze(dt);
+            if audio_clock {
+                viewport.set_time(Some(frame.timestamp));
+            }
+            let analyzer = audio.analyzer_mut();
+            viewport.set_band_energy(analyzer.bands(dt));
+            viewport.set_beat(analyzer.onset(dt));
+            viewport.set_meters(analyzer.visual_meters());
+            let spectrum = analyzer.spectrum(dt).to_vec();
+            viewport.set_spectrum(&analyzer.log_spectrum(SPECTRUM_SIZE));
+            let (rms, loudness) = frame.visual_levels();
 
-            log::trace!("got RMS in redraw() {rms:?} {loudness}");
+            log::trace!(
+                "got RMS in redraw() {:?} {:?} {rms:?} {loudness}",
+                frame.timestamp,
+                frame.rms_db
+            );
 
             //let egui_input = gui.window_state.take_egui_input(&window);
 
-            viewport.update(dt, (rms, loudness));
+            viewport.update(dt, (rms, loudness), frame.visual_peak(), &spectrum);
             //viewport.render(egui_input).unwrap();
-            viewport.render(&mut gui, &window).unwrap();
+            if let Err(e) = viewport.render(&mut gui, &window) {
+                log::error!("can't render: {e}");
+                *control_flow = ControlFlow::Exit;
+            }
         }
 
         Event::MainEventsCleared => window.request_redraw(),
 
+        Event::LoopDestroyed => {
+            settings.volume = gui.volume();
+            settings.palette = gui.palette();
+            let size = window.inner_size();
+            settings.window_size = Some((size.width, size.height));
+            if let Some(path) = &settings_path {
+                if let Err(e) = settings.save(path) {
+                    log::error!("can't save settings to {}: {e}", path.display());
+                }
+            }
+        }
+
         _ => (),
     });
 }
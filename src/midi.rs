@@ -0,0 +1,161 @@
+use std::{thread, time::Duration};
+
+use crossbeam::channel::Sender;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+use crate::VizParam;
+
+/// MIDI clock ticks per quarter note.
+const TICKS_PER_BEAT: u32 = 24;
+
+/// How often to check that the port is still there, or has come back.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const CLIENT_NAME: &str = "time2freq";
+
+/// Turns MIDI messages into `VizParam`s: clock into beats and tempo, and control changes
+/// into the parameters they're mapped to.
+#[derive(Debug, Clone)]
+pub struct MidiMapping {
+    /// Parameter set by each controller number, with the value scaled from `0..=127` to
+    /// `0.0..=1.0`.
+    controls: Vec<(u8, String)>,
+    /// Clock ticks since the last beat.
+    ticks: u32,
+    /// When the last beat's first tick arrived.
+    beat_start: Option<Duration>,
+}
+
+impl MidiMapping {
+    pub fn new(controls: Vec<(u8, String)>) -> Self {
+        Self {
+            controls,
+            ticks: 0,
+            beat_start: None,
+        }
+    }
+
+    /// Parameters set by `message`, received at `at` on any steady clock.
+    ///
+    /// Every 24th clock tick is a `VizParam::Beat`, counting from a Start, and from the
+    /// second on comes with the tempo since the one before. Stop zeroes the tempo.
+    pub fn translate(&mut self, message: &[u8], at: Duration) -> Vec<VizParam> {
+        let mut params = Vec::new();
+        match *message {
+            // Timing clock.
+            [0xf8, ..] => {
+                if self.ticks == 0 {
+                    if let Some(start) = self.beat_start {
+                        let beat = at.saturating_sub(start).as_secs_f32();
+                        if beat > 0.0 {
+                            params.push(VizParam::Bpm(60.0 / beat));
+                        }
+                    }
+                    self.beat_start = Some(at);
+                    params.push(VizParam::Beat);
+                }
+                self.ticks = (self.ticks + 1) % TICKS_PER_BEAT;
+            }
+            // Start, so the next tick is a downbeat.
+            [0xfa, ..] => {
+                self.ticks = 0;
+                self.beat_start = None;
+            }
+            // Stop.
+            [0xfc, ..] => {
+                self.ticks = 0;
+                self.beat_start = None;
+                params.push(VizParam::Bpm(0.0));
+            }
+            // Control change on any channel.
+            [status, controller, value, ..] if status & 0xf0 == 0xb0 => {
+                for (_, name) in self.controls.iter().filter(|(cc, _)| *cc == controller) {
+                    match VizParam::parse(name, value as f32 / 127.0) {
+                        Ok(param) => params.push(param),
+                        Err(e) => log::warn!("midi cc {controller}: {e}"),
+                    }
+                }
+            }
+            _ => (),
+        }
+        params
+    }
+}
+
+/// Names of the MIDI inputs `input` sees now. The list is read afresh each time.
+fn names_of(input: &MidiInput) -> Vec<String> {
+    input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect()
+}
+
+/// Translate messages from the first MIDI input whose name contains `name` with
+/// `mapping`, sending what's set to `params`, on a thread of its own.
+///
+/// The port needn't be there yet. If it goes away it's waited for, and reconnected to
+/// when it comes back, with the clock starting over.
+pub fn listen(name: String, mapping: MidiMapping, params: Sender<VizParam>) -> anyhow::Result<()> {
+    // One client to watch the ports with, rather than a new one every poll.
+    let watcher = MidiInput::new(CLIENT_NAME)?;
+    let ports = names_of(&watcher);
+    if !ports.iter().any(|port| port.contains(&name)) {
+        log::warn!("no midi input matching {name:?} yet, have {ports:?}");
+    }
+
+    thread::spawn(move || {
+        let mut connection: Option<MidiInputConnection<MidiMapping>> = None;
+        loop {
+            let present = names_of(&watcher).iter().any(|port| port.contains(&name));
+            match (present, connection.take()) {
+                (true, Some(open)) => connection = Some(open),
+                (false, Some(open)) => {
+                    log::warn!("midi input {name:?} went away, waiting for it");
+                    open.close();
+                }
+                (true, None) => match connect(&name, mapping.clone(), params.clone()) {
+                    Ok(open) => connection = Some(open),
+                    Err(e) => log::error!("can't open midi input {name:?}: {e}"),
+                },
+                (false, None) => (),
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+fn connect(
+    name: &str,
+    mapping: MidiMapping,
+    params: Sender<VizParam>,
+) -> anyhow::Result<MidiInputConnection<MidiMapping>> {
+    let mut input = MidiInput::new(CLIENT_NAME)?;
+    // Clock is among what's ignored by default.
+    input.ignore(Ignore::None);
+    let (port, port_name) = input
+        .ports()
+        .into_iter()
+        .find_map(|port| {
+            let port_name = input.port_name(&port).ok()?;
+            port_name.contains(name).then_some((port, port_name))
+        })
+        .ok_or_else(|| anyhow::anyhow!("it's gone"))?;
+
+    let connection = input
+        .connect(
+            &port,
+            CLIENT_NAME,
+            move |micros, message, mapping: &mut MidiMapping| {
+                for param in mapping.translate(message, Duration::from_micros(micros)) {
+                    // Only fails once the event loop has gone, taking the process with it.
+                    let _ = params.send(param);
+                }
+            },
+            mapping,
+        )
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    log::info!("midi input: {port_name}");
+    Ok(connection)
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+/// How long the flux average that onsets stand out against remembers.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(1);
+
+/// How many mean deviations above the average flux counts as an onset.
+const SENSITIVITY: f32 = 1.5;
+
+/// Floor on the threshold so noise in near-silence doesn't read as beats.
+const MIN_THRESHOLD: f32 = 1e-2;
+
+/// Compression applied to magnitudes before differencing, as `ln(1 + COMPRESSION * m)`,
+/// so quiet partials count as well as the loudest one.
+const COMPRESSION: f32 = 100.0;
+
+/// Beat detection by spectral flux: how much the spectrum grew since the last frame,
+/// compared against its recent average.
+#[derive(Debug, Clone)]
+pub struct OnsetDetector {
+    window: Duration,
+    previous: Vec<f32>,
+    mean: f32,
+    deviation: f32,
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl OnsetDetector {
+    /// An onset has to stand out from the flux averaged over about `window`.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            previous: Vec::new(),
+            mean: 0.0,
+            deviation: 0.0,
+        }
+    }
+
+    /// Take the next spectrum, `dt` after the last, and return how strongly it's an
+    /// onset: 0 below the adaptive threshold, rising to 1 at twice the threshold.
+    pub fn process(&mut self, magnitudes: &[f32], dt: Duration) -> f32 {
+        let compressed = magnitudes.iter().map(|m| (1.0 + COMPRESSION * m).ln());
+
+        // A new spectrum size has nothing to compare against yet.
+        if self.previous.len() != magnitudes.len() {
+            self.previous = compressed.collect();
+            return 0.0;
+        }
+
+        // Skip DC, which only tracks offset.
+        let mut flux = 0.0;
+        for (i, (previous, magnitude)) in self.previous.iter_mut().zip(compressed).enumerate() {
+            if i > 0 {
+                flux += (magnitude - *previous).max(0.0);
+            }
+            *previous = magnitude;
+        }
+
+        let threshold = (self.mean + SENSITIVITY * self.deviation).max(MIN_THRESHOLD);
+        let onset = ((flux - threshold) / threshold).clamp(0.0, 1.0);
+
+        let coeff = if self.window.is_zero() {
+            1.0
+        } else {
+            1.0 - (-dt.as_secs_f32() / self.window.as_secs_f32()).exp()
+        };
+        self.deviation += ((flux - self.mean).abs() - self.deviation) * coeff;
+        self.mean += (flux - self.mean) * coeff;
+
+        onset
+    }
+
+    /// Forget the history, e.g. between tracks.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.mean = 0.0;
+        self.deviation = 0.0;
+    }
+}
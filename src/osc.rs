@@ -0,0 +1,100 @@
+use std::{net::UdpSocket, thread};
+
+use crossbeam::channel::Sender;
+use rosc::{OscMessage, OscPacket, OscType};
+
+use crate::VizParam;
+
+/// Address prefix of the messages the visualizer answers to, followed by a
+/// `VizParam::parse` name: `/viz/stereo_mix`, `/viz/palette/0/r`.
+const PREFIX: &str = "/viz/";
+
+/// Parameters set by `packet`, in order, with an error for each message that doesn't
+/// name one. A bundle's messages apply right away, whatever its time tag.
+///
+/// A message takes its value from its first argument, which can be any number or a
+/// bool. `/viz/palette/<stop>` also takes three, setting the stop's red, green, and blue.
+pub fn params(packet: &OscPacket) -> Vec<Result<VizParam, String>> {
+    match packet {
+        OscPacket::Message(message) => message_params(message),
+        OscPacket::Bundle(bundle) => bundle.content.iter().flat_map(params).collect(),
+    }
+}
+
+fn message_params(message: &OscMessage) -> Vec<Result<VizParam, String>> {
+    let Some(name) = message.addr.strip_prefix(PREFIX) else {
+        return vec![Err(format!("{}: not under {PREFIX}", message.addr))];
+    };
+    let values: Result<Vec<f32>, String> = message
+        .args
+        .iter()
+        .map(|arg| value(arg).ok_or_else(|| format!("{}: {arg:?} isn't a number", message.addr)))
+        .collect();
+    let values = match values {
+        Ok(values) => values,
+        Err(e) => return vec![Err(e)],
+    };
+
+    let is_stop = name
+        .strip_prefix("palette/")
+        .is_some_and(|stop| !stop.contains('/'));
+    match values[..] {
+        [r, g, b] if is_stop => ["r", "g", "b"]
+            .into_iter()
+            .zip([r, g, b])
+            .map(|(channel, value)| VizParam::parse(&format!("{name}/{channel}"), value))
+            .collect(),
+        [value, ..] => vec![VizParam::parse(name, value)],
+        [] => vec![Err(format!("{}: no value", message.addr))],
+    }
+}
+
+fn value(arg: &OscType) -> Option<f32> {
+    match *arg {
+        OscType::Float(value) => Some(value),
+        OscType::Double(value) => Some(value as f32),
+        OscType::Int(value) => Some(value as f32),
+        OscType::Long(value) => Some(value as f32),
+        OscType::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Listen for OSC over UDP on `port` on a thread of its own, sending what's set to
+/// `params`. Packets that don't decode, and messages that don't name a parameter, are
+/// logged and skipped.
+pub fn listen(port: u16, params: Sender<VizParam>) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    log::info!("listening for osc on {}", socket.local_addr()?);
+
+    thread::spawn(move || {
+        let mut buf = [0; rosc::decoder::MTU];
+        loop {
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) => {
+                    log::error!("osc: {e}");
+                    return;
+                }
+            };
+            let packet = match rosc::decoder::decode_udp(&buf[..len]) {
+                Ok((_, packet)) => packet,
+                Err(e) => {
+                    log::warn!("osc: bad packet: {e:?}");
+                    continue;
+                }
+            };
+            for param in self::params(&packet) {
+                match param {
+                    Ok(param) => {
+                        if params.send(param).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => log::warn!("osc: {e}"),
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,13 @@
+/// Three RGB color stops the shader blends between, from quiet to loud.
+pub type Palette = [[f32; 3]; 3];
+
+/// Built-in palettes by name. The first is the default.
+pub const PALETTE_PRESETS: &[(&str, Palette)] = &[
+    (
+        "classic",
+        [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+    ),
+    ("ember", [[0.3, 0.0, 0.0], [1.0, 0.4, 0.0], [1.0, 0.9, 0.3]]),
+    ("ice", [[0.0, 0.05, 0.3], [0.0, 0.7, 0.9], [0.9, 1.0, 1.0]]),
+    ("mono", [[0.1, 0.1, 0.1], [0.5, 0.5, 0.5], [1.0, 1.0, 1.0]]),
+];
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use crate::{audio::MAX_VOLUME, PALETTE_PRESETS};
+
+/// A visualizer setting changed at runtime from outside the window, e.g. by a script
+/// on stdin, for the event loop to apply on the next frame. Values are already clamped
+/// to the setting's range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VizParam {
+    /// Playback volume, `0.0..=MAX_VOLUME`.
+    Volume(f32),
+    /// Gain on the analysis levels only, at least 0.
+    VisualGain(f32),
+    /// Stereo width of the meters, from 0 (mono) to 1.
+    StereoMix(f32),
+    /// Index into `PALETTE_PRESETS`.
+    Palette(usize),
+    /// One red, green, or blue `channel` of one color `stop`, from 0 to 1.
+    PaletteColor {
+        stop: usize,
+        channel: usize,
+        value: f32,
+    },
+    /// One component of `u.user`.
+    User(usize, f32),
+    LoudnessAttack(Duration),
+    LoudnessRelease(Duration),
+    /// Tempo of an external clock, for `u.bpm`.
+    Bpm(f32),
+    /// A beat of an external clock, shown as a full-strength `u.beat` for a frame.
+    Beat,
+}
+
+impl VizParam {
+    /// The parameter called `name` set to `value`, which is clamped to its range.
+    ///
+    /// Names are `volume`, `visual_gain`, `stereo_mix`, `palette` (a preset index),
+    /// `palette/<stop>/<r|g|b>`, `user/<0-3>`, `loudness_attack` and `loudness_release`
+    /// in milliseconds, `bpm`, and `beat`, which ignores the value.
+    pub fn parse(name: &str, value: f32) -> Result<Self, String> {
+        if !value.is_finite() {
+            return Err(format!("{name}: {value} isn't a number"));
+        }
+        let index = |part: Option<&str>, len: usize| {
+            part.and_then(|part| part.parse().ok())
+                .filter(|index| *index < len)
+                .ok_or_else(|| format!("{name}: expected an index below {len}"))
+        };
+        let millis = || Duration::from_secs_f32(value.max(0.0) / 1000.0);
+
+        let mut parts = name.split('/');
+        let param = match parts.next().unwrap_or_default() {
+            "volume" => Self::Volume(value.clamp(0.0, MAX_VOLUME)),
+            "visual_gain" => Self::VisualGain(value.max(0.0)),
+            "stereo_mix" => Self::StereoMix(value.clamp(0.0, 1.0)),
+            "palette" if name == "palette" => {
+                let preset = value.round().max(0.0) as usize;
+                Self::Palette(preset.min(PALETTE_PRESETS.len() - 1))
+            }
+            "palette" => {
+                let stop = index(parts.next(), 3)?;
+                let channel = match parts.next() {
+                    Some("r") => 0,
+                    Some("g") => 1,
+                    Some("b") => 2,
+                    _ => return Err(format!("{name}: expected r, g, or b after the stop")),
+                };
+                Self::PaletteColor {
+                    stop,
+                    channel,
+                    value: value.clamp(0.0, 1.0),
+                }
+            }
+            "user" => Self::User(index(parts.next(), 4)?, value),
+            "loudness_attack" => Self::LoudnessAttack(millis()),
+            "loudness_release" => Self::LoudnessRelease(millis()),
+            "bpm" => Self::Bpm(value.max(0.0)),
+            "beat" => Self::Beat,
+            _ => return Err(format!("unknown parameter {name}")),
+        };
+        if parts.next().is_some() {
+            return Err(format!("unknown parameter {name}"));
+        }
+        Ok(param)
+    }
+}
+
+/// A `name value` line, as `VizParam::parse` takes them.
+impl std::str::FromStr for VizParam {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return Err(format!("expected a name and a value, got {s:?}"));
+        };
+        let value = value.parse().map_err(|e| format!("{value}: {e}"))?;
+        Self::parse(name, value)
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Analyzer, Palette, Viewport};
+
+/// A complete look to share: a scene shader with the palette and analysis settings it
+/// was tuned for, read from TOML. Whatever a preset leaves out stays as it was.
+///
+/// ```toml
+/// shader = "aurora.wgsl"
+/// palette = [[0.0, 0.0, 0.2], [0.0, 0.8, 0.6], [1.0, 1.0, 0.8]]
+/// background = [0.02, 0.02, 0.05]
+/// loudness_smoothing_ms = [10, 400]
+/// band_smoothing_ms = 80
+/// crossovers = [200, 3000]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    /// Scene shader, a WGSL file relative to the preset.
+    pub shader: Option<PathBuf>,
+    /// Scene shader as WGSL inline, instead of `shader`.
+    pub shader_source: Option<String>,
+    pub palette: Option<Palette>,
+    /// Red, green, and blue behind the scene, from 0 to 1.
+    pub background: Option<[f32; 3]>,
+    /// Attack and release of the loudness driving the background.
+    pub loudness_smoothing_ms: Option<[f32; 2]>,
+    /// Time constant of the band energy smoothing.
+    pub band_smoothing_ms: Option<f32>,
+    /// Low-mid and mid-high band crossovers in Hz.
+    pub crossovers: Option<[f32; 2]>,
+}
+
+impl Preset {
+    /// Read and check the preset at `path`, along with the shader file it names, leaving
+    /// only shader compile errors and crossovers above Nyquist for when it's applied.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut preset = Self::parse(&std::fs::read_to_string(path)?)?;
+        if let Some(shader) = &preset.shader {
+            let shader = path.parent().unwrap_or(Path::new("")).join(shader);
+            let source = std::fs::read_to_string(&shader)
+                .map_err(|e| anyhow::anyhow!("can't read shader {}: {e}", shader.display()))?;
+            preset.shader_source = Some(source);
+            preset.shader = Some(shader);
+        }
+        Ok(preset)
+    }
+
+    /// A preset from TOML `text`, checked with `validate`. A relative `shader` is left for
+    /// `load` to read, which keeps the path and fills in `shader_source`.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let preset: Self = toml::from_str(text)?;
+        if preset.shader.is_some() && preset.shader_source.is_some() {
+            anyhow::bail!("give shader or shader_source, not both");
+        }
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    /// Check that every value is in range, without compiling the shader.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let colors = self.palette.iter().flatten().chain(&self.background);
+        if colors.flatten().any(|c| !(0.0..=1.0).contains(c)) {
+            anyhow::bail!("colors must be from 0 to 1");
+        }
+        let times = self.loudness_smoothing_ms.iter().flatten();
+        if times
+            .chain(&self.band_smoothing_ms)
+            .any(|ms| !(*ms >= 0.0 && ms.is_finite()))
+        {
+            anyhow::bail!("smoothing times must be at least 0 ms");
+        }
+        if let Some([low, high]) = self.crossovers {
+            if !(low > 0.0 && low < high && high.is_finite()) {
+                anyhow::bail!("crossovers must satisfy 0 < low < high, got {low} and {high} Hz");
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap in the shader and set the rest of the look. A shader that doesn't compile is
+    /// an error before anything has changed.
+    pub fn apply_to_viewport(&self, viewport: &mut Viewport) -> anyhow::Result<()> {
+        if let Some(source) = &self.shader_source {
+            viewport.reload_shader(source)?;
+        }
+        if let Some(palette) = self.palette {
+            viewport.set_palette(palette);
+        }
+        if let Some(background) = self.background {
+            viewport.set_clear_color(background);
+        }
+        if let Some([attack, release]) = self.loudness_smoothing_ms {
+            viewport.set_loudness_smoothing(millis(attack), millis(release));
+        }
+        Ok(())
+    }
+
+    /// Set the analysis. Crossovers above Nyquist for the analyzer's sample rate are an
+    /// error before anything has changed.
+    pub fn apply_to_analyzer(&self, analyzer: &mut Analyzer) -> anyhow::Result<()> {
+        if let Some([low, high]) = self.crossovers {
+            analyzer.set_crossovers(low, high)?;
+        }
+        if let Some(tau) = self.band_smoothing_ms {
+            analyzer.set_band_smoothing(millis(tau));
+        }
+        Ok(())
+    }
+}
+
+fn millis(ms: f32) -> Duration {
+    Duration::from_secs_f32(ms / 1000.0)
+}
@@ -1,24 +1,157 @@
 #![allow(unused)]
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Error, Result};
+use crossbeam::channel::{self, RecvTimeoutError};
 use symphonia::{
     core::{
         audio::SampleBuffer,
         codecs::{Decoder, DecoderOptions},
-        errors::Error::DecodeError,
-        formats::{FormatOptions, FormatReader},
-        io::MediaSourceStream,
-        meta::MetadataOptions,
+        errors::Error::{DecodeError, IoError},
+        formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+        io::{MediaSource, MediaSourceStream, ReadOnlySource},
+        meta::{MetadataOptions, MetadataRevision, StandardTagKey},
         probe::Hint,
+        units::Time,
     },
     default::{get_codecs, get_probe},
 };
 
+use crate::error::{Result, Time2FreqError};
+
+/// Size of each read from a network stream.
+const HTTP_CHUNK: usize = 16 * 1024;
+
+/// Chunks of a network stream read ahead of the decoder, 1 MiB or about a minute of
+/// 128 kbps MP3, to ride out stalls in the connection.
+const HTTP_READ_AHEAD: usize = 64;
+
+/// How long a network stream may take to connect, or go without sending anything, before
+/// it's an error.
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a read waiting on the network checks whether it's been cancelled.
+const HTTP_CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// The tags a player shows, from whichever of the file's metadata blocks set them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl Tags {
+    fn read(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            let value = tag.value.to_string();
+            if !value.trim().is_empty() {
+                *field = Some(value);
+            }
+        }
+    }
+}
+
+/// Whether `path` is a URL `AudioFile::open` streams rather than a file.
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// A reader filled ahead of time by a thread of its own, which ends once this is dropped.
+struct ReadAhead {
+    chunks: channel::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    offset: usize,
+    /// Checked while waiting on the network, so a stop doesn't hang on a stalled stream.
+    cancelled: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl ReadAhead {
+    fn new(
+        mut inner: impl Read + Send + 'static,
+        cancelled: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let (send, chunks) = channel::bounded(HTTP_READ_AHEAD);
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; HTTP_CHUNK];
+            let result = match inner.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    Ok(chunk)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if send.send(result).is_err() || failed {
+                return;
+            }
+        });
+        Self {
+            chunks,
+            chunk: Vec::new(),
+            offset: 0,
+            cancelled: Box::new(cancelled),
+        }
+    }
+
+    /// The next chunk, or `None` at the end of the stream.
+    fn next_chunk(&self) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + HTTP_READ_TIMEOUT;
+        loop {
+            match self.chunks.recv_timeout(HTTP_CANCEL_POLL) {
+                Ok(chunk) => return chunk.map(Some),
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if (self.cancelled)() {
+                return Err(io::Error::other("stream cancelled"));
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "stream stopped sending",
+                ));
+            }
+        }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.chunk.len() {
+            match self.next_chunk()? {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+            self.offset = 0;
+        }
+        let len = buf.len().min(self.chunk.len() - self.offset);
+        buf[..len].copy_from_slice(&self.chunk[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
 pub struct AudioFile {
     format: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     default_track_id: u32,
+    tags: Tags,
 }
 
 impl AudioFile {
@@ -26,75 +159,168 @@ impl AudioFile {
         [
             ("sample_rate".to_string(), self.sample_rate().to_string()),
             ("channels".to_string(), self.channels().to_string()),
+            ("title".to_string(), self.tags.title.clone()),
+            ("artist".to_string(), self.tags.artist.clone()),
+            ("album".to_string(), self.tags.album.clone()),
         ]
-        .into()
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+    }
+
+    pub fn tags(&self) -> &Tags {
+        &self.tags
     }
 
     pub fn sample_rate(&self) -> u32 {
         self.decoder.codec_params().sample_rate.unwrap()
     }
 
+    /// Track length from the frame count, if the container reports it.
+    pub fn duration(&self) -> Option<Duration> {
+        let params = self.decoder.codec_params();
+        let frames = params.n_frames?;
+        let sample_rate = params.sample_rate?;
+        Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+    }
+
     pub fn channels(&self) -> usize {
         self.decoder.codec_params().channels.unwrap().count()
     }
 
+    /// Open a file, or stream `http://` and `https://` URLs. Streams can't seek.
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let src = Box::new(File::open(path)?);
+        Self::open_cancellable(path, || false)
+    }
+
+    /// Like `open`, but a stream stops waiting on the network as soon as `cancelled`
+    /// returns true, failing the read it was on.
+    pub fn open_cancellable(
+        path: impl AsRef<Path>,
+        cancelled: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let mut hint = Hint::new();
+        let src: Box<dyn MediaSource> = match path.to_str().filter(|path| is_url(path)) {
+            Some(url) => {
+                let agent = ureq::AgentBuilder::new()
+                    .timeout_connect(HTTP_CONNECT_TIMEOUT)
+                    .timeout_read(HTTP_READ_TIMEOUT)
+                    .build();
+                let response = agent.get(url).call().map_err(Box::new)?;
+                hint.mime_type(response.content_type());
+                let reader = ReadAhead::new(response.into_reader(), cancelled);
+                Box::new(ReadOnlySource::new(reader))
+            }
+            None => Box::new(File::open(path)?),
+        };
         let mss = MediaSourceStream::new(src, Default::default());
-        let hint = Hint::new();
         let format_opts: FormatOptions = Default::default();
         let metadata_opts: MetadataOptions = Default::default();
         let decoder_opts: DecoderOptions = Default::default();
-        let format = get_probe()
+        let mut probed = get_probe()
             .format(&hint, mss, &format_opts, &metadata_opts)
-            .unwrap()
-            .format;
+            .map_err(Time2FreqError::UnsupportedFormat)?;
+        let mut format = probed.format;
+
+        // Tags can come before the container (e.g. ID3v2 on an MP3) or inside it, with the
+        // container's taking precedence.
+        let mut tags = Tags::default();
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            tags.read(revision);
+        }
+        if let Some(revision) = format.metadata().current() {
+            tags.read(revision);
+        }
+
         let track = format
             .default_track()
-            .ok_or_else(|| Error::msg("No default track."))?;
-        let decoder = get_codecs().make(&track.codec_params, &decoder_opts)?;
+            .ok_or(Time2FreqError::NoDefaultTrack)?;
+        let decoder = get_codecs()
+            .make(&track.codec_params, &decoder_opts)
+            .map_err(Time2FreqError::UnsupportedCodec)?;
+
+        // `sample_rate` and `channels` rely on these being known up front.
+        let params = decoder.codec_params();
+        if params.sample_rate.is_none() || params.channels.is_none() {
+            return Err(Time2FreqError::UnknownLayout);
+        }
         let default_track_id = track.id;
 
         Ok(AudioFile {
             format,
             decoder,
             default_track_id,
+            tags,
         })
     }
 
+    /// Decode the next buffer of the default track, or `None` at the end of the stream.
+    ///
+    /// Packets from other tracks are skipped, as are packets that fail to decode, which
+    /// are logged and leave a short gap rather than ending the track.
     pub fn next_sample(&mut self, meth: CopyMethod) -> Result<Option<SampleBuffer<f32>>> {
-        let packet = self.format.next_packet()?;
-        if packet.track_id() != self.default_track_id {
-            return Ok(None);
-        }
-        match self.decoder.decode(&packet) {
-            Ok(audio_buf_ref) => {
-                let spec = *audio_buf_ref.spec();
-                let duration = audio_buf_ref.capacity() as u64;
-                let mut buf = SampleBuffer::new(duration, spec);
-                if let CopyMethod::Interleaved = meth {
-                    buf.copy_interleaved_ref(audio_buf_ref);
-                } else if let CopyMethod::Planar = meth {
-                    buf.copy_planar_ref(audio_buf_ref);
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(None)
                 }
-                Ok(Some(buf))
+                Err(e) => return Err(Time2FreqError::DecodeFailed(e)),
+            };
+            if packet.track_id() != self.default_track_id {
+                continue;
             }
-            Err(DecodeError(_)) => Ok(None),
-            Err(_) => Err(Error::msg("Decode error.")),
+            match self.decoder.decode(&packet) {
+                Ok(audio_buf_ref) => {
+                    let spec = *audio_buf_ref.spec();
+                    let duration = audio_buf_ref.capacity() as u64;
+                    let mut buf = SampleBuffer::new(duration, spec);
+                    if let CopyMethod::Interleaved = meth {
+                        buf.copy_interleaved_ref(audio_buf_ref);
+                    } else if let CopyMethod::Planar = meth {
+                        buf.copy_planar_ref(audio_buf_ref);
+                    }
+                    return Ok(Some(buf));
+                }
+                Err(DecodeError(e)) => log::warn!("skipping bad packet: {e}"),
+                Err(e) => return Err(Time2FreqError::DecodeFailed(e)),
+            }
+        }
+    }
+
+    /// Jump to `ts` from the start of the track and reset the decoder.
+    pub fn seek(&mut self, ts: Duration) -> Result<()> {
+        let time = Time::new(ts.as_secs(), ts.subsec_nanos() as f64 / 1e9);
+        match self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(self.default_track_id),
+            },
+        ) {
+            Ok(_) => {
+                self.decoder.reset();
+                Ok(())
+            }
+            Err(source) => Err(Time2FreqError::SeekFailed {
+                position: ts,
+                source,
+            }),
         }
     }
 
-    pub fn dump(&mut self) -> (Vec<f32>, Vec<f32>) {
-        let mut left = Vec::new();
-        let mut right = Vec::new();
-        while let Ok(buf) = self.next_sample(CopyMethod::Planar) {
-            if let Some(buf) = buf {
-                let s = buf.samples();
-                left.append(&mut Vec::from(&s[..s.len() / 2]));
-                right.append(&mut Vec::from(&s[s.len() / 2..]));
+    /// Decode the rest of the track, one `Vec` of samples per channel.
+    pub fn dump(&mut self) -> Vec<Vec<f32>> {
+        let mut channels = vec![Vec::new(); self.channels()];
+        while let Ok(Some(buf)) = self.next_sample(CopyMethod::Interleaved) {
+            for frame in buf.samples().chunks(channels.len()) {
+                for (channel, sample) in channels.iter_mut().zip(frame) {
+                    channel.push(*sample);
+                }
             }
         }
-        (left, right)
+        channels
     }
 }
 
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Palette, PALETTE_PRESETS};
+
+/// Preferences kept between runs in a TOML file. Fields missing from the file take their
+/// defaults, so older files keep loading as fields are added.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub volume: f32,
+    pub latency_ms: usize,
+    /// Output device by name, or the default if unset.
+    pub device: Option<String>,
+    /// Scene shader, or the built-in one if unset.
+    pub shader: Option<PathBuf>,
+    pub palette: Palette,
+    /// Inner size of the window in physical pixels.
+    pub window_size: Option<(u32, u32)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            latency_ms: 100,
+            device: None,
+            shader: None,
+            palette: PALETTE_PRESETS[0].1,
+            window_size: None,
+        }
+    }
+}
+
+impl Settings {
+    /// `settings.toml` in the platform's config directory, if there is one.
+    pub fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "time2freq")
+            .map(|dirs| dirs.config_dir().join("settings.toml"))
+    }
+
+    /// Read settings from `path`. A missing file gives the defaults, as does one that can't
+    /// be read or parsed, after logging why.
+    pub fn load(path: &Path) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("can't read {}: {e}", path.display());
+                return Self::default();
+            }
+        };
+
+        toml::from_str(&text).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid settings in {}: {e}", path.display());
+            Self::default()
+        })
+    }
+
+    /// Write settings to `path`, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    // Position within the spectrogram quad, with the newest column at the top.
+    @location(1) uv: vec2<f32>,
+    @location(2) @interpolate(flat) spectrogram: u32,
 }
 
 struct Uniform {
@@ -9,17 +12,55 @@ struct Uniform {
     screen_size: vec2<f32>,
     time: f32,
     loudness: f32,
+    user: vec4<f32>,
+    peak: vec2<f32>,
+    band_energy: vec3<f32>,
+    spectrogram_row: u32,
+    stereo_mix: f32,
+    beat: f32,
+    mouse_down: f32,
+    palette: array<vec4<f32>, 3>,
+    level_fast: vec2<f32>,
+    level_slow: vec2<f32>,
+    bpm: f32,
 }
 @group(0) @binding(0)
 var<uniform> u: Uniform;
 
+// `SPECTRUM_SIZE` is defined by the Rust side when the shader is loaded.
+@group(0) @binding(1)
+var<storage, read> spectrum: array<f32, SPECTRUM_SIZE>;
+
+// One spectrum per row, written as a ring starting at `u.spectrogram_row`.
+@group(1) @binding(0)
+var spectrogram: texture_2d<f32>;
+
+// Magnitude of `bin` from `age` columns before the newest, unwrapping the ring so callers
+// can index history linearly.
+fn spectrogram_at(bin: u32, age: u32) -> f32 {
+    let size = textureDimensions(spectrogram);
+    let row = (u.spectrogram_row + size.y - 1u - min(age, size.y - 1u)) % size.y;
+    return textureLoad(spectrogram, vec2<i32>(i32(min(bin, size.x - 1u)), i32(row)), 0).r;
+}
+
+// Blend from the first palette stop at 0 through the second at 0.5 to the third at 1.
+fn palette_at(t: f32) -> vec3<f32> {
+    let x = 2.0 * clamp(t, 0.0, 1.0);
+    if x < 1.0 {
+        return mix(u.palette[0].rgb, u.palette[1].rgb, x);
+    }
+    return mix(u.palette[1].rgb, u.palette[2].rgb, x - 1.0);
+}
+
 @vertex
 fn vs_main(
     @builtin(vertex_index) in_vertex_index: u32,
 ) -> VertexOutput {
     let lvl_0 = 0.9 * u.level[0];
     let lvl_1 = 0.9 * u.level[1];
-    var vertices = array<vec2<f32>, 8>(
+    let peak_0 = 0.9 * u.peak[0];
+    let fast_0 = 0.9 * u.level_fast[0];
+    var vertices = array<vec2<f32>, 20>(
         vec2<f32>(-0.5, lvl_0),
         vec2<f32>(-0.5, -0.9),
         vec2<f32>(-0.1, lvl_0),
@@ -28,24 +69,35 @@ fn vs_main(
         vec2<f32>(0.1, -0.9),
         vec2<f32>(0.5, u.loudness), //lvl_1),
         vec2<f32>(0.5, -0.9),
-    );
-
-    var colors = array<vec4<f32>, 8>(
-        vec4<f32>(0.0, 0.0, 1.0, 1.0),
-        vec4<f32>(0.0, 1.0, 0.0, 1.0),
-        vec4<f32>(1.0, 0.0, 0.0, 1.0),
-        vec4<f32>(0.0, 0.0, 1.0, 1.0),
-        vec4<f32>(1.0, 0.0, 0.0, 1.0),
-        vec4<f32>(0.0, 1.0, 0.0, 1.0),
-        vec4<f32>(0.0, 0.0, 1.0, 1.0),
-        vec4<f32>(0.0, 0.0, 1.0, 1.0),
+        // Peak-hold tick over the left channel bar.
+        vec2<f32>(-0.5, peak_0 + 0.01),
+        vec2<f32>(-0.5, peak_0 - 0.01),
+        vec2<f32>(-0.1, peak_0 + 0.01),
+        vec2<f32>(-0.1, peak_0 - 0.01),
+        // Spectrogram behind everything.
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        // Fast meter needle over the left channel bar.
+        vec2<f32>(-0.45, fast_0 + 0.005),
+        vec2<f32>(-0.45, fast_0 - 0.005),
+        vec2<f32>(-0.15, fast_0 + 0.005),
+        vec2<f32>(-0.15, fast_0 - 0.005),
     );
 
     let v = vertices[in_vertex_index];
 
     var out: VertexOutput;
     out.clip_position = vec4<f32>(v, 0.0, 1.0);
-    out.color = colors[in_vertex_index];
+    // Bars run through the palette from the bottom up to their level. The peak tick and
+    // fast needle are white, and the spectrogram picks its colors per pixel.
+    out.color = vec4<f32>(1.0);
+    if in_vertex_index < 8u {
+        out.color = vec4<f32>(palette_at((v.y + 0.9) / 1.8), 1.0);
+    }
+    out.uv = vec2<f32>(0.5 + 0.5 * v.x, 0.5 - 0.5 * v.y);
+    out.spectrogram = select(0u, 1u, in_vertex_index >= 12u && in_vertex_index < 16u);
     return out;
 }
 
@@ -55,12 +107,20 @@ fn fs_main(
 ) -> @location(0) vec4<f32> {
     let color = in.color;
 
-    var mouse_fade =
-        distance(in.clip_position.xy, u.mouse_pos) / max(u.screen_size.x, u.screen_size.y);
+    if in.spectrogram == 1u {
+        let size = vec2<f32>(textureDimensions(spectrogram));
+        // Already scaled to 0..1 by `Analyzer::spectrum`.
+        let intensity = spectrogram_at(u32(in.uv.x * size.x), u32(in.uv.y * size.y));
+        return vec4<f32>(palette_at(intensity) * intensity * 0.5, 1.0);
+    }
+
+    // Both in 0..1 with y down, so no flip is needed.
+    var mouse_fade = distance(in.clip_position.xy / u.screen_size, u.mouse_pos);
 
     var y_fade = f32(in.clip_position.y) / u.screen_size.y;
 
     //return color * pow(y_fade, 2.0) * mouse_fade;
-    return color * pow(y_fade, 2.0);
+    // Brighten the bars while the mouse is held down.
+    return color * pow(y_fade, 2.0) * (1.0 + 0.5 * u.mouse_down);
     //return color;
 }
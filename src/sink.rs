@@ -0,0 +1,161 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use crossbeam::sync::{Parker, Unparker};
+
+use crate::audio::RING_CHANNELS;
+
+/// Where the decode thread sends interleaved stereo frames.
+pub trait OutputSink: Send {
+    /// How many samples `write` can take right now. The decoder waits for room for a
+    /// whole frame before writing it.
+    fn space(&self) -> usize;
+
+    /// Take one frame, which must fit in `space`.
+    fn write(&mut self, frame: &[f32]) -> Result<()>;
+
+    /// Whether samples wait in a buffer for an output callback, rather than counting as
+    /// played as soon as they're written.
+    fn buffers(&self) -> bool;
+
+    /// Block until there may be more `space`, or at most `timeout`.
+    fn wait(&mut self, timeout: Duration) {
+        std::thread::sleep(timeout);
+    }
+}
+
+/// The ring buffer drained by the cpal output callback.
+pub struct Device {
+    producer: rtrb::Producer<f32>,
+    parker: Parker,
+}
+
+impl Device {
+    pub fn new(producer: rtrb::Producer<f32>) -> Self {
+        Self {
+            producer,
+            parker: Parker::new(),
+        }
+    }
+
+    /// Handle for the consumer to wake a waiting writer once it has drained some samples.
+    pub fn unparker(&self) -> &Unparker {
+        self.parker.unparker()
+    }
+}
+
+impl OutputSink for Device {
+    fn space(&self) -> usize {
+        self.producer.slots()
+    }
+
+    fn write(&mut self, frame: &[f32]) -> Result<()> {
+        for sample in frame {
+            self.producer
+                .push(*sample)
+                .map_err(|_| anyhow::anyhow!("device buffer is full"))?;
+        }
+        Ok(())
+    }
+
+    fn buffers(&self) -> bool {
+        true
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        self.parker.park_timeout(timeout);
+    }
+}
+
+/// A 32-bit float stereo WAV file, written no faster than real time.
+pub struct WavFile {
+    writer: hound::WavWriter<BufWriter<File>>,
+    sample_rate: u32,
+    ahead: Duration,
+    started: Option<Instant>,
+    written: u64,
+}
+
+impl WavFile {
+    /// Create the file, letting the writer run up to `ahead` in front of the clock.
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32, ahead: Duration) -> Result<Self> {
+        let spec = hound::WavSpec {
+            channels: RING_CHANNELS as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        Ok(Self {
+            writer: hound::WavWriter::create(path, spec)?,
+            sample_rate,
+            ahead,
+            started: None,
+            written: 0,
+        })
+    }
+
+    /// Write the header and close the file. Dropping does the same but ignores errors.
+    pub fn finalize(self) -> Result<()> {
+        Ok(self.writer.finalize()?)
+    }
+
+    fn position(&self) -> Duration {
+        let frames = self.written / RING_CHANNELS as u64;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
+
+impl OutputSink for WavFile {
+    fn space(&self) -> usize {
+        match self.started {
+            Some(started) if self.position() > started.elapsed() + self.ahead => 0,
+            _ => usize::MAX,
+        }
+    }
+
+    fn write(&mut self, frame: &[f32]) -> Result<()> {
+        let now = Instant::now();
+        let elapsed = now - *self.started.get_or_insert(now);
+        let position = self.position();
+
+        // Restart the clock after falling behind, e.g. while paused, instead of rushing to
+        // catch up.
+        if elapsed > position + self.ahead {
+            self.started = now.checked_sub(position);
+        }
+
+        for sample in frame {
+            self.writer.write_sample(*sample)?;
+        }
+        self.written += frame.len() as u64;
+
+        Ok(())
+    }
+
+    fn buffers(&self) -> bool {
+        false
+    }
+}
+
+/// Discards everything as fast as it comes, for analysis without any output.
+pub struct Null;
+
+impl OutputSink for Null {
+    fn space(&self) -> usize {
+        usize::MAX
+    }
+
+    fn write(&mut self, _frame: &[f32]) -> Result<()> {
+        Ok(())
+    }
+
+    fn buffers(&self) -> bool {
+        false
+    }
+}
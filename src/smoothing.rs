@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+/// One-pole smoother with separate rise and fall time constants.
+#[derive(Debug, Clone, Copy)]
+pub struct AttackRelease {
+    attack: Duration,
+    release: Duration,
+    value: f32,
+}
+
+impl AttackRelease {
+    pub fn new(attack: Duration, release: Duration) -> Self {
+        Self {
+            attack,
+            release,
+            value: 0.0,
+        }
+    }
+
+    pub fn set_times(&mut self, attack: Duration, release: Duration) {
+        self.attack = attack;
+        self.release = release;
+    }
+
+    pub fn times(&self) -> (Duration, Duration) {
+        (self.attack, self.release)
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Drop straight back to zero.
+    pub fn reset(&mut self) {
+        self.value = 0.0;
+    }
+
+    /// Move toward `target` by the fraction one time constant covers in `dt`. A zero time
+    /// constant jumps straight to the target.
+    pub fn process(&mut self, target: f32, dt: Duration) -> f32 {
+        let tau = if target > self.value {
+            self.attack
+        } else {
+            self.release
+        };
+
+        if tau.is_zero() {
+            self.value = target;
+        } else {
+            let coeff = 1.0 - (-dt.as_secs_f32() / tau.as_secs_f32()).exp();
+            self.value += (target - self.value) * coeff;
+        }
+
+        self.value
+    }
+}
+
+/// Peak-hold indicator that sticks at the highest recent value for `hold`, then falls at
+/// `decay` per second, in the input's units, until the input catches it.
+#[derive(Debug, Clone, Copy)]
+pub struct PeakHold {
+    hold: Duration,
+    decay: f32,
+    value: f32,
+    held_for: Duration,
+}
+
+impl PeakHold {
+    pub fn new(hold: Duration, decay: f32) -> Self {
+        Self {
+            hold,
+            decay,
+            value: f32::NEG_INFINITY,
+            held_for: Duration::ZERO,
+        }
+    }
+
+    pub fn set_timing(&mut self, hold: Duration, decay: f32) {
+        self.hold = hold;
+        self.decay = decay;
+    }
+
+    pub fn timing(&self) -> (Duration, f32) {
+        (self.hold, self.decay)
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Take a new peak reading `dt` after the last.
+    pub fn process(&mut self, peak: f32, dt: Duration) -> f32 {
+        if peak >= self.value {
+            self.value = peak;
+            self.held_for = Duration::ZERO;
+        } else {
+            self.held_for += dt;
+            if self.held_for > self.hold {
+                self.value = (self.value - self.decay * dt.as_secs_f32()).max(peak);
+            }
+        }
+
+        self.value
+    }
+}
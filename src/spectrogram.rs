@@ -0,0 +1,167 @@
+use crate::wgpu;
+
+/// Columns of history kept by default, about four seconds at 60 fps.
+pub const DEFAULT_HISTORY: u32 = 256;
+
+/// Scrolling spectrum history for the shader's `spectrogram` texture.
+///
+/// Each row of the texture holds one spectrum, written in place at a ring offset rather
+/// than shifting the image. The shader reads rows back in time order with
+/// `spectrogram_at`, given the offset from `row`.
+pub struct Spectrogram {
+    texture: wgpu::Texture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    bins: usize,
+    width: u32,
+    height: u32,
+    row: u32,
+    column: Vec<f32>,
+}
+
+impl Spectrogram {
+    pub fn new(device: &wgpu::Device, bins: usize, history: u32) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spectrogram bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let width = Self::clamp_dimension(device, bins as u32);
+        let height = Self::clamp_dimension(device, history);
+        let (texture, bind_group) = Self::create_texture(device, &bind_group_layout, width, height);
+
+        Self {
+            texture,
+            bind_group_layout,
+            bind_group,
+            bins,
+            width,
+            height,
+            row: 0,
+            column: vec![0.0; width as usize],
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// The row the next column goes to; the newest column is the one before it.
+    pub fn row(&self) -> u32 {
+        self.row
+    }
+
+    pub fn history(&self) -> u32 {
+        self.height
+    }
+
+    /// Reallocate for `bins` per column and `history` columns, clearing the image.
+    pub fn resize(&mut self, device: &wgpu::Device, bins: usize, history: u32) {
+        self.bins = bins;
+        self.width = Self::clamp_dimension(device, bins as u32);
+        self.height = Self::clamp_dimension(device, history);
+        (self.texture, self.bind_group) =
+            Self::create_texture(device, &self.bind_group_layout, self.width, self.height);
+        self.row = 0;
+        self.column = vec![0.0; self.width as usize];
+    }
+
+    /// Keep `history` columns at the current width, clearing the image.
+    pub fn set_history(&mut self, device: &wgpu::Device, history: u32) {
+        self.resize(device, self.bins, history);
+    }
+
+    /// Write `spectrum` as the newest column. A spectrum of a different length, e.g. after
+    /// changing the FFT size, starts a new image. Spectra wider than the device allows are
+    /// decimated to fit.
+    pub fn push(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, spectrum: &[f32]) {
+        if spectrum.is_empty() {
+            return;
+        }
+        if spectrum.len() != self.bins {
+            self.resize(device, spectrum.len(), self.height);
+        }
+
+        let step = spectrum.len() as f32 / self.width as f32;
+        for (i, out) in self.column.iter_mut().enumerate() {
+            *out = spectrum[(i as f32 * step) as usize];
+        }
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: self.row,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.column),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * std::mem::size_of::<f32>() as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.row = (self.row + 1) % self.height;
+    }
+
+    fn clamp_dimension(device: &wgpu::Device, size: u32) -> u32 {
+        size.clamp(1, device.limits().max_texture_dimension_2d)
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Spectrogram texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Spectrogram bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        (texture, bind_group)
+    }
+}
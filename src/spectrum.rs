@@ -0,0 +1,379 @@
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc, time::Duration};
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+pub const DEFAULT_FFT_SIZE: usize = 2048;
+
+/// Default span of the log-spaced bands in Hz, skipping the mostly-empty top octave.
+pub const DEFAULT_BAND_RANGE: (f32, f32) = (20.0, 16_000.0);
+
+/// Default bass/mid and mid/treble crossovers in Hz for `split_bands`.
+pub const DEFAULT_CROSSOVERS: (f32, f32) = (250.0, 4_000.0);
+
+/// Sum magnitudes into bass, mid, and treble, split at `crossovers` in Hz. The DC bin is
+/// left out.
+pub fn split_bands(magnitudes: &[f32], bin_width: f32, crossovers: (f32, f32)) -> [f32; 3] {
+    let mut bands = [0.0; 3];
+    for (i, magnitude) in magnitudes.iter().enumerate().skip(1) {
+        let freq = i as f32 * bin_width;
+        let band = if freq < crossovers.0 {
+            0
+        } else if freq < crossovers.1 {
+            1
+        } else {
+            2
+        };
+        bands[band] += magnitude;
+    }
+    bands
+}
+
+/// Window applied to the analysis samples before the transform.
+///
+/// Windows with lower sidelobes leak less energy into neighbouring bins but widen the main
+/// lobe, so peaks smear over more bins. Every window also scales the signal down by its
+/// coherent gain (the mean coefficient), which `Spectrum` divides back out so a full-scale
+/// sine reads 1.0 whichever window is chosen:
+///
+/// | window          | coherent gain | correction |
+/// |-----------------|---------------|------------|
+/// | Rectangular     | 1.0           | 1.0x       |
+/// | Hann            | 0.5           | 2.0x       |
+/// | Hamming         | 0.54          | 1.85x      |
+/// | Blackman-Harris | 0.359         | 2.79x      |
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFn {
+    /// Best resolution, worst leakage.
+    Rectangular,
+    #[default]
+    Hann,
+    /// Lower first sidelobe than Hann, but sidelobes fall off slowly.
+    Hamming,
+    /// 4-term, about -92 dB sidelobes, for wide dynamic range at the cost of resolution.
+    BlackmanHarris,
+}
+
+impl WindowFn {
+    pub const ALL: [WindowFn; 4] = [
+        WindowFn::Rectangular,
+        WindowFn::Hann,
+        WindowFn::Hamming,
+        WindowFn::BlackmanHarris,
+    ];
+
+    /// The following window in `ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|w| *w == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WindowFn::Rectangular => "Rectangular",
+            WindowFn::Hann => "Hann",
+            WindowFn::Hamming => "Hamming",
+            WindowFn::BlackmanHarris => "Blackman-Harris",
+        }
+    }
+
+    fn coefficients(self, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / len as f32;
+                match self {
+                    WindowFn::Rectangular => 1.0,
+                    WindowFn::Hann => 0.5 - 0.5 * x.cos(),
+                    WindowFn::Hamming => 0.54 - 0.46 * x.cos(),
+                    WindowFn::BlackmanHarris => {
+                        0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos()
+                            - 0.01168 * (3.0 * x).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Remap linear FFT magnitudes into the buckets between consecutive `edges` in Hz.
+///
+/// A bucket spanning whole bins takes the loudest of them, so narrow peaks still show in
+/// wide buckets. A bucket narrower than a bin, common at the low end, interpolates between
+/// the two bins around its center instead of repeating one bin's value.
+pub fn log_buckets(magnitudes: &[f32], bin_width: f32, edges: &[f32]) -> Vec<f32> {
+    edges
+        .windows(2)
+        .map(|edge| {
+            let (low, high) = (edge[0] / bin_width, edge[1] / bin_width);
+            let first = low.ceil() as usize;
+            let last = (high.ceil() as usize).min(magnitudes.len());
+
+            if first < last {
+                magnitudes[first..last]
+                    .iter()
+                    .fold(0f32, |max, m| max.max(*m))
+            } else {
+                let center = (low + high) / 2.0;
+                let i = center as usize;
+                let below = magnitudes.get(i).copied().unwrap_or(0.0);
+                let above = magnitudes.get(i + 1).copied().unwrap_or(below);
+                below + (above - below) * center.fract()
+            }
+        })
+        .collect()
+}
+
+/// How `Spectrum::process_smoothed` blends each bin with its history, independent of the
+/// frame rate.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SmoothingMode {
+    /// Raw magnitudes, which flicker from frame to frame.
+    #[default]
+    Off,
+    /// Bins jump up to new peaks and fall back by keeping this fraction per second, the
+    /// classic analyzer look.
+    PeakDecay(f32),
+    /// Exponential moving average with this time constant, which also softens attacks.
+    Ema(Duration),
+}
+
+/// How spectrum magnitudes map onto `0.0..=1.0` for display, between a floor and a
+/// ceiling given in the scale's own units: dBFS for `Db`, linear magnitude otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MagnitudeScale {
+    /// Proportional to magnitude, where quiet detail all but disappears.
+    #[default]
+    Linear,
+    /// Proportional to level in dB, how we hear loudness.
+    Db,
+    /// Linear, then raised to this power. Below 1 lifts quiet bins.
+    Power(f32),
+}
+
+impl MagnitudeScale {
+    pub fn apply(self, magnitude: f32, floor: f32, ceiling: f32) -> f32 {
+        let value = match self {
+            Self::Db => crate::audio::to_db(magnitude),
+            Self::Linear | Self::Power(_) => magnitude,
+        };
+        let normalized = ((value - floor) / (ceiling - floor)).clamp(0.0, 1.0);
+        match self {
+            Self::Power(gamma) => normalized.powf(gamma),
+            Self::Linear | Self::Db => normalized,
+        }
+    }
+}
+
+/// Magnitude spectrum over the most recent `fft_size` mono samples.
+pub struct Spectrum {
+    fft_size: usize,
+    zero_pad: usize,
+    window_fn: WindowFn,
+    band_range: (f32, f32),
+    planner: FftPlanner<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_sum: f32,
+    input: VecDeque<f32>,
+    buf: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    /// Set when `magnitudes` no longer matches the input, window, or transform, so
+    /// `process` only runs the FFT once however many readers there are between pushes.
+    stale: bool,
+    smoothing: SmoothingMode,
+    smoothed: Vec<f32>,
+}
+
+impl Spectrum {
+    pub fn new(fft_size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let window_fn = WindowFn::default();
+        let window = window_fn.coefficients(fft_size);
+        let window_sum = window.iter().sum();
+
+        Self {
+            fft_size,
+            zero_pad: 1,
+            window_fn,
+            band_range: DEFAULT_BAND_RANGE,
+            planner,
+            fft,
+            window,
+            window_sum,
+            input: VecDeque::from(vec![0.0; fft_size]),
+            buf: vec![Complex::default(); fft_size],
+            magnitudes: vec![0.0; fft_size / 2 + 1],
+            stale: true,
+            smoothing: SmoothingMode::default(),
+            smoothed: vec![0.0; fft_size / 2 + 1],
+        }
+    }
+
+    /// Transform at `fft_size * factor` points, zero-filling past the analysis window.
+    ///
+    /// This interpolates the frequency axis without lengthening the window, so bins get
+    /// narrower but time smearing stays the same.
+    pub fn set_zero_pad(&mut self, factor: usize) {
+        let factor = factor.max(1);
+        if factor == self.zero_pad {
+            return;
+        }
+
+        self.zero_pad = factor;
+        self.plan();
+    }
+
+    /// Analyze the last `fft_size` samples, starting over from silence.
+    ///
+    /// Longer windows resolve finer frequencies, `sample_rate / fft_size` apart, but
+    /// smear each change over the whole window, so the spectrum reacts later and beats
+    /// blur together.
+    pub fn set_fft_size(&mut self, fft_size: usize) {
+        let fft_size = fft_size.max(1);
+        if fft_size == self.fft_size {
+            return;
+        }
+
+        self.fft_size = fft_size;
+        self.window = self.window_fn.coefficients(fft_size);
+        self.window_sum = self.window.iter().sum();
+        self.input = VecDeque::from(vec![0.0; fft_size]);
+        self.plan();
+    }
+
+    /// Replan the transform for the current size and padding.
+    fn plan(&mut self) {
+        let len = self.fft_size * self.zero_pad;
+        self.fft = self.planner.plan_fft_forward(len);
+        self.buf = vec![Complex::default(); len];
+        self.magnitudes = vec![0.0; len / 2 + 1];
+        self.smoothed = vec![0.0; len / 2 + 1];
+        self.stale = true;
+    }
+
+    /// Swap the analysis window, recomputing its coefficients once.
+    pub fn set_window(&mut self, window_fn: WindowFn) {
+        self.window_fn = window_fn;
+        self.window = window_fn.coefficients(self.fft_size);
+        self.window_sum = self.window.iter().sum();
+        self.stale = true;
+    }
+
+    pub fn window(&self) -> WindowFn {
+        self.window_fn
+    }
+
+    pub fn zero_pad(&self) -> usize {
+        self.zero_pad
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Set the lowest and highest frequency in Hz covered by the bands.
+    pub fn set_band_range(&mut self, low_hz: f32, high_hz: f32) {
+        self.band_range = (low_hz, high_hz);
+    }
+
+    pub fn band_range(&self) -> (f32, f32) {
+        self.band_range
+    }
+
+    /// `n_bands + 1` logarithmically spaced band edges in Hz across the band range.
+    pub fn band_edges(&self, n_bands: usize) -> Vec<f32> {
+        let (low, high) = self.band_range;
+        let octaves = (high / low).log2();
+        (0..=n_bands)
+            .map(|i| low * 2f32.powf(octaves * i as f32 / n_bands as f32))
+            .collect()
+    }
+
+    /// Frequency spacing between output bins.
+    pub fn bin_width(&self, sample_rate: u32) -> f32 {
+        sample_rate as f32 / self.buf.len() as f32
+    }
+
+    /// Forget the buffered input and smoothing, as if fed silence.
+    pub fn clear(&mut self) {
+        self.input.iter_mut().for_each(|sample| *sample = 0.0);
+        self.smoothed.iter_mut().for_each(|bin| *bin = 0.0);
+        self.stale = true;
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.input.pop_front();
+        self.input.push_back(sample);
+        self.stale = true;
+    }
+
+    /// Linear magnitudes from DC to Nyquist, scaled so a full-scale sine reads 1.0.
+    /// Transformed once per change to the input, so repeat calls are cheap.
+    pub fn process(&mut self) -> &[f32] {
+        if !self.stale {
+            return &self.magnitudes;
+        }
+        self.stale = false;
+
+        for (i, slot) in self.buf.iter_mut().enumerate() {
+            *slot = match self.input.get(i) {
+                Some(sample) => Complex::new(sample * self.window[i], 0.0),
+                None => Complex::default(),
+            };
+        }
+
+        self.fft.process(&mut self.buf);
+
+        // The window sum is the coherent gain times `fft_size`, so this undoes the window's
+        // attenuation. Padding adds only zeros to the sum, so normalizing by it (rather than
+        // the transform length) also keeps levels independent of the pad factor.
+        let scale = 2.0 / self.window_sum;
+        for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.buf) {
+            *magnitude = bin.norm() * scale;
+        }
+
+        &self.magnitudes
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: SmoothingMode) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn smoothing(&self) -> SmoothingMode {
+        self.smoothing
+    }
+
+    /// The output of the last `process_smoothed`, without advancing the smoothing.
+    pub fn smoothed(&self) -> &[f32] {
+        &self.smoothed
+    }
+
+    /// `process`, blended with previous calls by the smoothing mode over `dt` since the
+    /// last call.
+    pub fn process_smoothed(&mut self, dt: Duration) -> &[f32] {
+        self.process();
+
+        let secs = dt.as_secs_f32();
+        match self.smoothing {
+            SmoothingMode::Off => self.smoothed.copy_from_slice(&self.magnitudes),
+            SmoothingMode::PeakDecay(decay) => {
+                let retained = decay.clamp(0.0, 1.0).powf(secs);
+                for (smoothed, magnitude) in self.smoothed.iter_mut().zip(&self.magnitudes) {
+                    *smoothed = magnitude.max(*smoothed * retained);
+                }
+            }
+            SmoothingMode::Ema(tau) => {
+                let coeff = if tau.is_zero() {
+                    1.0
+                } else {
+                    1.0 - (-secs / tau.as_secs_f32()).exp()
+                };
+                for (smoothed, magnitude) in self.smoothed.iter_mut().zip(&self.magnitudes) {
+                    *smoothed += (magnitude - *smoothed) * coeff;
+                }
+            }
+        }
+
+        &self.smoothed
+    }
+}
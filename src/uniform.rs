@@ -1,30 +1,86 @@
-use bytemuck::Zeroable;
 use crate::wgpu::util::DeviceExt;
+use bytemuck::Zeroable;
 
-use crate::wgpu;
+use crate::{wgpu, Palette, PALETTE_PRESETS};
 
+/// Length of the spectrum array bound at `@binding(1)`, prepended to the shader source as
+/// `SPECTRUM_SIZE` so Rust and WGSL agree.
+pub const SPECTRUM_SIZE: usize = 64;
+
+/// The shader's `Uniform` struct, byte for byte.
+///
+/// WGSL lays out uniform buffers like std140: scalars align to 4 bytes, `vec2` to 8, and
+/// `vec3` and `vec4` to 16, with the struct padded to a multiple of 16. `repr(C)` only
+/// aligns to 4, so the `_pad` fields fill the gaps WGSL leaves. New fields go at the end,
+/// with `tests/uniform.rs` checking every offset against `shader.wgsl`.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct UniformRaw {
     pub level: [f32; 2],
+    /// Cursor position as a fraction of the window, from (0, 0) at the top left to (1, 1)
+    /// at the bottom right. Y runs down as in framebuffer coordinates, so it lines up with
+    /// `@builtin(position).xy / screen_size`; flip it for a y-up convention.
     pub mouse_pos: [f32; 2],
     pub screen_size: [f32; 2],
     pub time: f32,
     pub loudness: f32,
-    _pad: f64,
-    //_pad: f32,
+    /// Free for embedders to drive their own shaders; the built-in shader ignores it.
+    pub user: [f32; 4],
+    /// Held peak level per channel, on the same scale as `level`.
+    pub peak: [f32; 2],
+    _pad0: [f32; 2],
+    /// Smoothed bass, mid, and treble energy.
+    pub band_energy: [f32; 3],
+    /// Ring offset of the spectrogram texture, the row its next column goes to.
+    pub spectrogram_row: u32,
+    /// How much of the stereo difference `level` and `peak` keep, from 0 (both channels
+    /// show the mono average) to 1 (full stereo).
+    pub stereo_mix: f32,
+    /// Onset strength of the current frame, 0 to 1.
+    pub beat: f32,
+    /// 1 while the left mouse button is held over the scene, otherwise 0.
+    pub mouse_down: f32,
+    _pad1: f32,
+    /// Color stops for the bars and spectrogram, as RGB with alpha unused.
+    pub palette: [[f32; 4]; 3],
+    /// RMS per channel with fast ballistics, on the same scale as `level`. The built-in
+    /// shader draws it as a needle over the left channel bar.
+    pub level_fast: [f32; 2],
+    /// RMS per channel with slow, VU-like ballistics, on the same scale as `level`. The
+    /// built-in shader doesn't use it; it's there for custom shaders.
+    pub level_slow: [f32; 2],
+    /// Tempo of an external MIDI clock in beats per minute, or 0 without one.
+    pub bpm: f32,
+    _pad2: [f32; 3],
+}
+
+const _: () = assert!(std::mem::size_of::<UniformRaw>() % 16 == 0);
+
+impl UniformRaw {
+    pub fn set_palette(&mut self, palette: Palette) {
+        for (stop, [r, g, b]) in self.palette.iter_mut().zip(palette) {
+            *stop = [r, g, b, 1.0];
+        }
+    }
 }
 
 pub struct Uniform {
     pub raw: UniformRaw,
+    /// Spectrum bars for the shader's `spectrum` storage buffer.
+    pub spectrum: [f32; SPECTRUM_SIZE],
     buffer: wgpu::Buffer,
+    spectrum_buffer: wgpu::Buffer,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
 }
 
 impl Uniform {
     pub fn new(device: &wgpu::Device) -> Self {
-        let raw = UniformRaw::zeroed();
+        let mut raw = UniformRaw {
+            stereo_mix: 1.0,
+            ..UniformRaw::zeroed()
+        };
+        raw.set_palette(PALETTE_PRESETS[0].1);
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -32,32 +88,59 @@ impl Uniform {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let spectrum = [0.0; SPECTRUM_SIZE];
+        let spectrum_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Spectrum buffer"),
+            contents: bytemuck::cast_slice(&spectrum),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spectrum_buffer.as_entire_binding(),
+                },
+            ],
         });
 
         Self {
             raw,
+            spectrum,
             buffer,
+            spectrum_buffer,
             bind_group_layout,
             bind_group,
         }
@@ -73,5 +156,10 @@ impl Uniform {
 
     pub fn write_buffer(&mut self, queue: &wgpu::Queue) {
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.raw]));
+        queue.write_buffer(
+            &self.spectrum_buffer,
+            0,
+            bytemuck::cast_slice(&self.spectrum),
+        );
     }
 }
@@ -1,8 +1,17 @@
 use std::time::{Duration, Instant};
 
-use winit::{dpi::PhysicalSize, window::Window};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
+};
 
-use crate::{gui::Gui, Uniform, wgpu};
+use crate::{
+    gui::Gui,
+    offscreen::Offscreen,
+    spectrogram::{self, Spectrogram},
+    spectrum::DEFAULT_FFT_SIZE,
+    wgpu, AttackRelease, Palette, PeakHold, Uniform, UniformRaw, SPECTRUM_SIZE,
+};
 use noize::{Ease, PNoise1};
 
 pub struct Viewport {
@@ -13,20 +22,121 @@ pub struct Viewport {
     pub device: wgpu::Device,
     queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
-    shader: wgpu::ShaderModule,
+    adapter_info: wgpu::AdapterInfo,
+    present_modes: Vec<wgpu::PresentMode>,
+    pipeline: wgpu::RenderPipeline,
     pub uniform: Uniform,
-    #[allow(unused)]
+    /// Drive the levels while the music is silent or stopped, so the scene keeps moving.
     noise: (PNoise1, PNoise1),
+    idle_threshold: Option<f32>,
     start_time: Instant,
+    /// Overrides the wall clock for `u.time` when set.
+    time: Option<Duration>,
+    render_scale: f32,
+    offscreen: Option<Offscreen>,
+    /// What the scene pass clears to before drawing, behind anything the shader leaves.
+    clear_color: wgpu::Color,
+    loudness_smoothing: AttackRelease,
+    /// Analysis held by `set_frozen`, while frozen and then while thawing.
+    frozen: Option<Frozen>,
+    /// Whether freezing also stops `u.time`.
+    freeze_time: bool,
+    /// Set by `pulse_beat` until the next `update` puts `u.beat` at full strength.
+    beat_pulse: bool,
+    peak_hold: [PeakHold; 2],
+    spectrogram: Spectrogram,
+}
+
+/// The analysis in the uniform when the scene was frozen.
+struct Frozen {
+    raw: UniformRaw,
+    spectrum: [f32; SPECTRUM_SIZE],
+    since: Instant,
+    /// Time since unfreezing, while blending back to live analysis.
+    thaw: Option<Duration>,
+}
+
+/// How long unfreezing takes to blend from the held analysis back to live, so the scene
+/// doesn't jump.
+const THAW_TIME: Duration = Duration::from_millis(200);
+
+/// How long the peak indicator sticks before falling.
+const DEFAULT_PEAK_HOLD: Duration = Duration::from_secs(1);
+
+/// How fast the peak indicator falls, in level units per second (5 dB/s).
+const DEFAULT_PEAK_DECAY: f32 = 0.5;
+
+/// Attack and release of the loudness driving the background: quick enough to catch a
+/// hit, slow enough to fade rather than flicker between frames.
+const DEFAULT_LOUDNESS_SMOOTHING: (Duration, Duration) =
+    (Duration::from_millis(5), Duration::from_millis(250));
+
+/// Level, on the -1 to 1 scale of the uniform, below which `noise` starts taking over.
+/// About -18 dBFS.
+const DEFAULT_IDLE_THRESHOLD: f32 = -0.8;
+
+/// How far above the floor the idle noise moves the levels.
+const IDLE_DEPTH: f32 = 0.4;
+
+/// Bound on the levels written to the uniform. Nothing the audio side produces should
+/// come near it; it only keeps a bad value from reaching the shader as inf.
+const MAX_LEVEL: f32 = 100.0;
+
+/// The scene shader built into the crate.
+pub const DEFAULT_SHADER: &str = include_str!("shader.wgsl");
+
+/// How `Viewport::new_with_options` picks its GPU and surface.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuOptions {
+    /// Graphics APIs an adapter may use; all of them by default.
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Prefer an sRGB surface, the default, over a linear one.
+    pub srgb: bool,
+}
+
+impl Default for GpuOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            srgb: true,
+        }
+    }
 }
 
 impl Viewport {
     pub async fn new(window: &Window) -> Self {
+        Self::new_with_shader(window, DEFAULT_SHADER)
+            .await
+            .expect("built-in shader is valid")
+    }
+
+    /// Like `new`, drawing the scene with WGSL `source` instead of the built-in shader.
+    /// The shader gets the same bindings, including the `SPECTRUM_SIZE` constant.
+    pub async fn new_with_shader(window: &Window, source: &str) -> anyhow::Result<Self> {
+        Self::new_with_options(window, source, GpuOptions::default()).await
+    }
+
+    /// Like `new_with_shader`, choosing the backend, the adapter's power preference, and
+    /// between an sRGB and a linear surface.
+    ///
+    /// Shaders write linear color. On an sRGB surface, the default, the GPU gamma-encodes
+    /// it on the way out, so a fragment value of 0.5 shows at about 74% brightness and
+    /// blends happen in linear light. On a linear surface the value is shown as is, which
+    /// looks darker and more contrasty unless the shader applies its own gamma, e.g.
+    /// `pow(color, vec3(1.0 / 2.2))`. Either way falls back to the first format the
+    /// surface supports if it has none of the kind asked for.
+    pub async fn new_with_options(
+        window: &Window,
+        source: &str,
+        options: GpuOptions,
+    ) -> anyhow::Result<Self> {
         let size = window.inner_size();
         let scale_factor = window.scale_factor() as f32;
         //let instance = wgpu::Instance::new(wgpu::Backends::all());
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: options.backends,
             ..wgpu::InstanceDescriptor::default()
         });
 
@@ -35,16 +145,28 @@ impl Viewport {
 
         // SAFETY: `Viewport` is created in the main thread and `window` remains valid
         // for the lifetime of `surface`.
-        let surface = unsafe { instance.create_surface(window).unwrap() };
+        let surface = unsafe { instance.create_surface(window)? };
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: options.power_preference,
                 force_fallback_adapter: false,
                 compatible_surface: Some(&surface),
             })
             .await
-            .unwrap();
+            .ok_or_else(|| {
+                let found: Vec<String> = instance
+                    .enumerate_adapters(options.backends)
+                    .map(|adapter| describe_adapter(&adapter.get_info()))
+                    .collect();
+                anyhow::anyhow!(
+                    "no {:?} adapter can draw to this window, found: [{}]",
+                    options.backends,
+                    found.join("; ")
+                )
+            })?;
+        let adapter_info = adapter.get_info();
+        log::info!("adapter: {}", describe_adapter(&adapter_info));
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
@@ -55,51 +177,150 @@ impl Viewport {
                 None,
                 //Some(std::path::Path::new("/home/zach/projects/wgpu/time2freq/trace")),
             )
-            .await
-            .unwrap();
+            .await?;
 
         let capabilities = surface.get_capabilities(&adapter);
+        // The first format varies by platform, and isn't always sRGB.
+        let format = capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(|format| format.is_srgb() == options.srgb)
+            .unwrap_or(capabilities.formats[0]);
+        log::info!("surface format: {format:?}");
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: capabilities.formats[0],
+            format,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
             alpha_mode: capabilities.alpha_modes[0],
-            view_formats: vec![capabilities.formats[0]],
+            view_formats: vec![format],
         };
 
-
         surface.configure(&device, &config);
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
         let uniform = Uniform::new(&device);
+        let spectrogram = Spectrogram::new(
+            &device,
+            DEFAULT_FFT_SIZE / 2 + 1,
+            spectrogram::DEFAULT_HISTORY,
+        );
+        // Built once: the surface format is fixed here and the bind group layouts outlive
+        // any resizing of the resources behind them.
+        let pipeline = build_pipeline(
+            &device,
+            source,
+            config.format,
+            &[uniform.bind_group_layout(), spectrogram.bind_group_layout()],
+        )?;
         let noise = (
             PNoise1::new(47, 16, 1024, Ease::SmoothStep),
             PNoise1::new(42, 16, 1024, Ease::SmoothStep),
         );
 
-        Self {
+        Ok(Self {
             size,
             scale_factor,
             surface,
             device,
             queue,
             config,
-            shader,
+            adapter_info,
+            present_modes: capabilities.present_modes,
+            pipeline,
             uniform,
             noise,
+            idle_threshold: Some(DEFAULT_IDLE_THRESHOLD),
             start_time: Instant::now(),
+            time: None,
+            render_scale: 1.0,
+            offscreen: None,
+            clear_color: wgpu::Color::BLACK,
+            loudness_smoothing: AttackRelease::new(
+                DEFAULT_LOUDNESS_SMOOTHING.0,
+                DEFAULT_LOUDNESS_SMOOTHING.1,
+            ),
+            frozen: None,
+            freeze_time: false,
+            beat_pulse: false,
+            peak_hold: [PeakHold::new(DEFAULT_PEAK_HOLD, DEFAULT_PEAK_DECAY); 2],
+            spectrogram,
+        })
+    }
+
+    /// Render the shader at `scale` times the surface resolution and upscale it, keeping
+    /// the GUI at native resolution. Clamped to `0.25..=1.0`.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        let clamped = scale.clamp(0.25, 1.0);
+        if clamped != scale {
+            log::warn!("render scale {scale} out of range, using {clamped}");
         }
+        self.render_scale = clamped;
+
+        self.offscreen = if clamped < 1.0 {
+            Some(Offscreen::new(
+                &self.device,
+                self.config.format,
+                self.render_size(),
+            ))
+        } else {
+            None
+        };
+    }
+
+    /// The GPU drawing the viewport, for diagnosing rendering problems on a particular
+    /// machine.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Present with `mode`, falling back to `Fifo`, which every surface supports, if this
+    /// one can't. `Mailbox` and `Immediate` don't wait for vsync, so frames run uncapped.
+    /// The mode is kept across `resize`; the one actually in use is returned.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let selected = if self.present_modes.contains(&mode) {
+            mode
+        } else {
+            log::warn!(
+                "present mode {mode:?} not supported (only {:?}), using Fifo",
+                self.present_modes
+            );
+            wgpu::PresentMode::Fifo
+        };
+        log::info!("present mode: {selected:?}");
+
+        self.config.present_mode = selected;
+        self.surface.configure(&self.device, &self.config);
+        selected
     }
 
+    /// Size of the target the shader draws into.
+    fn render_size(&self) -> (u32, u32) {
+        let scale = |pixels: u32| ((pixels as f32 * self.render_scale).round() as u32).max(1);
+        (scale(self.config.width), scale(self.config.height))
+    }
+
+    /// Draw a frame. A lost, outdated, or timed out surface skips the frame, reconfiguring
+    /// the surface if needed, so only `OutOfMemory` comes back as an error.
     pub fn render(
         &self,
         gui: &mut Gui,
         window: &winit::window::Window,
     ) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            // The surface no longer matches the window, e.g. after minimizing.
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("timed out acquiring the surface texture, skipping frame");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -110,70 +331,15 @@ impl Viewport {
                 label: Some("Viewport::render() encoder"),
             });
 
-        let pipeline_layout = self
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[self.uniform.bind_group_layout()],
-                push_constant_ranges: &[],
-            });
-
-        let pipeline = self
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Viewport::render() pipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &self.shader,
-                    entry_point: "vs_main",
-                    buffers: &[],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    unclipped_depth: false,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &self.shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: self.config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-            });
+        let scene_view = match &self.offscreen {
+            Some(offscreen) => offscreen.view(),
+            None => &view,
+        };
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Viewport::render() render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::default()),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+        self.draw_scene(&mut encoder, scene_view);
 
-            render_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
-            render_pass.set_pipeline(&pipeline);
-            render_pass.draw(0..4, 0..1);
-            render_pass.draw(4..8, 0..1);
-            //render_pass.draw(0..8, 0..1);
+        if let Some(offscreen) = &self.offscreen {
+            offscreen.blit(&mut encoder, &view);
         }
 
         gui.render(
@@ -191,24 +357,526 @@ impl Viewport {
         Ok(())
     }
 
+    fn draw_scene(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Viewport::draw_scene() render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
+        render_pass.set_bind_group(1, self.spectrogram.bind_group(), &[]);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(12..16, 0..1);
+        render_pass.draw(0..4, 0..1);
+        render_pass.draw(4..8, 0..1);
+        render_pass.draw(8..12, 0..1);
+        render_pass.draw(16..20, 0..1);
+        //render_pass.draw(0..8, 0..1);
+    }
+
+    /// Draw the scene as of the last `update`, without the overlay, and read it back at the
+    /// resolution the shader renders at. Alpha isn't shown on screen, so the image is
+    /// made opaque.
+    pub fn capture(&self) -> anyhow::Result<image::RgbaImage> {
+        let bgra = match self.config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => anyhow::bail!("can't capture surface format {format:?}"),
+        };
+        let (width, height) = self.render_size();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // Surface textures can't always be copied from, so draw into one that can.
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Each row of the copy has to start on a multiple of the alignment.
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture buffer"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Viewport::capture() encoder"),
+            });
+        self.draw_scene(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()??;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+
+        for pixel in pixels.chunks_exact_mut(4) {
+            if bgra {
+                pixel.swap(0, 2);
+            }
+            pixel[3] = u8::MAX;
+        }
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("captured {width}x{height} pixels don't fit"))
+    }
+
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+
+            let render_size = self.render_size();
+            if let Some(offscreen) = &mut self.offscreen {
+                offscreen.resize(&self.device, render_size);
+            }
+        }
+    }
+
+    /// Smooth the loudness driving the background, independently of the level bars. It
+    /// rises with time constant `attack` and falls with `release`; zero times pass
+    /// loudness through unchanged. Defaults to 5 ms and 250 ms.
+    pub fn set_loudness_smoothing(&mut self, attack: Duration, release: Duration) {
+        self.loudness_smoothing.set_times(attack, release);
+    }
+
+    pub fn loudness_smoothing(&self) -> (Duration, Duration) {
+        self.loudness_smoothing.times()
+    }
+
+    /// Blend slow noise into the levels as they fall below `threshold`, on the uniform's
+    /// -1 to 1 scale, until it's all noise in silence, so the scene idles instead of going
+    /// still between tracks. `None` turns this off. Defaults to -0.8, about -18 dBFS.
+    pub fn set_idle_noise(&mut self, threshold: Option<f32>) {
+        self.idle_threshold = threshold.map(|threshold| threshold.clamp(-1.0, 1.0));
+    }
+
+    pub fn idle_noise(&self) -> Option<f32> {
+        self.idle_threshold
+    }
+
+    /// Background behind the scene, as red, green, and blue from 0 to 1 in the same space
+    /// as the palette. Defaults to black.
+    pub fn set_clear_color(&mut self, [r, g, b]: [f32; 3]) {
+        let [r, g, b] = [r, g, b].map(|c| c.clamp(0.0, 1.0) as f64);
+        self.clear_color = wgpu::Color { r, g, b, a: 1.0 };
+    }
+
+    pub fn clear_color(&self) -> [f32; 3] {
+        let wgpu::Color { r, g, b, .. } = self.clear_color;
+        [r, g, b].map(|c| c as f32)
+    }
+
+    /// Hold the scene on the analysis of the last `update`, while audio carries on. It
+    /// keeps rendering, and `u.time` keeps running unless `set_freeze_time` says otherwise.
+    /// Unfreezing blends back to live analysis over a moment rather than jumping to it.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        if frozen == self.frozen() {
+            return;
+        }
+        if frozen {
+            // Holding the uniform as it is also catches a thaw partway.
+            self.frozen = Some(Frozen {
+                raw: self.uniform.raw,
+                spectrum: self.uniform.spectrum,
+                since: Instant::now(),
+                thaw: None,
+            });
+        } else if let Some(frozen) = &mut self.frozen {
+            if self.freeze_time {
+                // Pick the clock up where it stopped.
+                self.start_time += frozen.since.elapsed();
+            }
+            frozen.thaw = Some(Duration::ZERO);
         }
     }
 
-    pub fn update(&mut self, _dt: Duration, level: ([f32; 2], f32)) {
-        //let level_left = self.noise.0.next().unwrap();
-        //let level_right = self.noise.1.next().unwrap();
-        //self.uniform.raw.level = [level_left, level_right];
-        self.uniform.raw.level = level.0;
-        self.uniform.raw.loudness = level.1;
-        self.uniform.raw.screen_size = [self.config.width as f32, self.config.height as f32];
-        self.uniform.raw.time = (Instant::now() - self.start_time).as_secs_f32();
+    pub fn frozen(&self) -> bool {
+        matches!(self.frozen, Some(Frozen { thaw: None, .. }))
+    }
+
+    /// Whether freezing stops `u.time` too, for a still frame. Off by default, so the
+    /// shader keeps animating over the held analysis.
+    pub fn set_freeze_time(&mut self, freeze_time: bool) {
+        if self.frozen() && freeze_time != self.freeze_time {
+            // Keep the clock continuous across the change.
+            if let Some(frozen) = &mut self.frozen {
+                if self.freeze_time {
+                    self.start_time += frozen.since.elapsed();
+                }
+                frozen.since = Instant::now();
+            }
+        }
+        self.freeze_time = freeze_time;
+    }
+
+    pub fn freeze_time(&self) -> bool {
+        self.freeze_time
+    }
+
+    /// Give the shader `time` as `u.time` from the next `update` on, instead of the time
+    /// since the viewport was created, so the same input renders the same frames. `None`
+    /// goes back to the wall clock.
+    pub fn set_time(&mut self, time: Option<Duration>) {
+        self.time = time;
+    }
+
+    /// How long the peak indicator holds before falling, and how fast it falls in level
+    /// units per second.
+    pub fn set_peak_hold(&mut self, hold: Duration, decay: f32) {
+        for peak_hold in &mut self.peak_hold {
+            peak_hold.set_timing(hold, decay);
+        }
+    }
+
+    /// Feed bass, mid, and treble energy to the shader as `u.band_energy`.
+    pub fn set_band_energy(&mut self, bands: [f32; 3]) {
+        self.uniform.raw.band_energy = bands;
+    }
+
+    /// Feed spectrum bars to the shader's `spectrum` array, zero-filling past the end of
+    /// `bars` and dropping any beyond `SPECTRUM_SIZE`.
+    pub fn set_spectrum(&mut self, bars: &[f32]) {
+        let spectrum = &mut self.uniform.spectrum;
+        let len = bars.len().min(SPECTRUM_SIZE);
+        spectrum[..len].copy_from_slice(&bars[..len]);
+        spectrum[len..].fill(0.0);
+    }
+
+    /// Narrow the level and peak display toward mono, from 0 (both sides show the
+    /// average) to 1 (full stereo, the default).
+    pub fn set_stereo_mix(&mut self, mix: f32) {
+        self.uniform.raw.stereo_mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Feed the cursor position within the window, in pixels from the top left, to the
+    /// shader as `u.mouse_pos` normalized to 0..1.
+    pub fn set_mouse_position(&mut self, position: PhysicalPosition<f64>) {
+        self.uniform.raw.mouse_pos = [
+            (position.x / self.size.width.max(1) as f64) as f32,
+            (position.y / self.size.height.max(1) as f64) as f32,
+        ];
+    }
+
+    /// Whether the left mouse button is held, as `u.mouse_down`.
+    pub fn set_mouse_down(&mut self, down: bool) {
+        self.uniform.raw.mouse_down = if down { 1.0 } else { 0.0 };
+    }
+
+    /// Recolor the bars and spectrogram, e.g. with one of `PALETTE_PRESETS`.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.uniform.raw.set_palette(palette);
+    }
+
+    /// Feed the fast and slow RMS meters, as from `Analyzer::visual_meters`, to the
+    /// shader as `u.level_fast` and `u.level_slow`, with the same stereo mix as `level`.
+    pub fn set_meters(&mut self, (fast, slow): ([f32; 2], [f32; 2])) {
+        let mix = self.uniform.raw.stereo_mix;
+        self.uniform.raw.level_fast = stereo_mix(fast.map(finite), mix);
+        self.uniform.raw.level_slow = stereo_mix(slow.map(finite), mix);
+    }
+
+    /// Feed onset strength to the shader as `u.beat`.
+    pub fn set_beat(&mut self, beat: f32) {
+        self.uniform.raw.beat = beat;
+    }
+
+    /// Put `u.beat` at full strength on the next `update`, whatever `set_beat` says, e.g.
+    /// on each beat of an external clock.
+    pub fn pulse_beat(&mut self) {
+        self.beat_pulse = true;
+    }
+
+    /// Feed the tempo of an external clock to the shader as `u.bpm`, 0 for none.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.uniform.raw.bpm = finite(bpm).max(0.0);
+    }
+
+    /// Pass arbitrary data to the shader as `u.user`. The built-in shader ignores it, so
+    /// this only has an effect with a custom shader that reads the field.
+    pub fn set_user_data(&mut self, user: [f32; 4]) {
+        self.uniform.raw.user = user;
+    }
+
+    /// Number of spectra the spectrogram scrolls through, clearing it.
+    pub fn set_spectrogram_history(&mut self, history: u32) {
+        self.spectrogram.set_history(&self.device, history);
+    }
+
+    /// Recompile the scene shader from WGSL `source` and swap it in. On a compile or
+    /// pipeline error the current shader keeps running and the error is returned.
+    pub fn reload_shader(&mut self, source: &str) -> anyhow::Result<()> {
+        self.pipeline = build_pipeline(
+            &self.device,
+            source,
+            self.config.format,
+            &[
+                self.uniform.bind_group_layout(),
+                self.spectrogram.bind_group_layout(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Advance the frame, adding `spectrum` (full-resolution magnitudes scaled to
+    /// `0.0..=1.0`, as from `Analyzer::spectrum`) as the newest spectrogram column.
+    pub fn update(
+        &mut self,
+        dt: Duration,
+        level: ([f32; 2], f32),
+        peak: [f32; 2],
+        spectrum: &[f32],
+    ) {
+        let frozen = self.frozen();
+        let beat_pulse = std::mem::take(&mut self.beat_pulse);
+        // Smoothing stands still while frozen, so it picks up from the held values.
+        if !frozen {
+            let mix = self.uniform.raw.stereo_mix;
+            let mut levels = stereo_mix(level.0.map(finite), mix);
+            if let Some(threshold) = self.idle_threshold {
+                let noise = [self.noise.0.next(), self.noise.1.next()];
+                for (level, noise) in levels.iter_mut().zip(noise) {
+                    *level = idle_blend(*level, noise.unwrap_or(0.0), threshold);
+                }
+            }
+            self.uniform.raw.level = levels;
+            self.uniform.raw.loudness = self.loudness_smoothing.process(finite(level.1), dt);
+            for (held, (peak_hold, peak)) in self.uniform.raw.peak.iter_mut().zip(
+                self.peak_hold
+                    .iter_mut()
+                    .zip(stereo_mix(peak.map(finite), mix)),
+            ) {
+                *held = peak_hold.process(peak, dt);
+            }
+            if beat_pulse {
+                self.uniform.raw.beat = 1.0;
+            }
+        }
+        let (width, height) = self.render_size();
+        self.uniform.raw.screen_size = [width as f32, height as f32];
+        if !(frozen && self.freeze_time) {
+            self.uniform.raw.time = self
+                .time
+                .unwrap_or_else(|| Instant::now() - self.start_time)
+                .as_secs_f32();
+        }
+
+        if !frozen {
+            self.spectrogram.push(&self.device, &self.queue, spectrum);
+            self.uniform.raw.spectrogram_row = self.spectrogram.row();
+        }
+        self.hold_analysis(dt);
 
         self.uniform.write_buffer(&self.queue);
     }
+
+    /// Put the held analysis back over whatever the setters fed in since freezing, or
+    /// blend from it to the live analysis while thawing.
+    fn hold_analysis(&mut self, dt: Duration) {
+        let Some(frozen) = &mut self.frozen else {
+            return;
+        };
+        let t = match &mut frozen.thaw {
+            None => 0.0,
+            Some(thaw) => {
+                *thaw += dt;
+                if *thaw >= THAW_TIME {
+                    self.frozen = None;
+                    return;
+                }
+                thaw.as_secs_f32() / THAW_TIME.as_secs_f32()
+            }
+        };
+        let blend = |held: f32, live: &mut f32| *live = held + (*live - held) * t;
+        let (held, raw) = (&frozen.raw, &mut self.uniform.raw);
+        let pairs: [(&[f32], &mut [f32]); 6] = [
+            (&held.level, &mut raw.level),
+            (&held.peak, &mut raw.peak),
+            (&held.band_energy, &mut raw.band_energy),
+            (&held.level_fast, &mut raw.level_fast),
+            (&held.level_slow, &mut raw.level_slow),
+            (&frozen.spectrum, &mut self.uniform.spectrum),
+        ];
+        for (held, live) in pairs {
+            held.iter()
+                .zip(live)
+                .for_each(|(held, live)| blend(*held, live));
+        }
+        blend(held.loudness, &mut raw.loudness);
+        blend(held.beat, &mut raw.beat);
+    }
+}
+
+/// Name, backend, and driver of an adapter, for logs and errors.
+fn describe_adapter(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{} ({:?}, {:?}, driver {} {})",
+        info.name, info.backend, info.device_type, info.driver, info.driver_info
+    )
+}
+
+/// Clamp a level to `±MAX_LEVEL`, reading NaN as the bottom of the scale.
+fn finite(level: f32) -> f32 {
+    if level.is_nan() {
+        -1.0
+    } else {
+        level.clamp(-MAX_LEVEL, MAX_LEVEL)
+    }
+}
+
+/// Fade `level` toward idle motion from `noise`, in `-1.0..=1.0`, as it falls from
+/// `threshold` to the floor at -1.
+fn idle_blend(level: f32, noise: f32, threshold: f32) -> f32 {
+    let depth = threshold + 1.0;
+    if depth <= 0.0 || level >= threshold {
+        return level;
+    }
+    let blend = ((threshold - level) / depth).clamp(0.0, 1.0);
+    let idle = -1.0 + IDLE_DEPTH * (noise.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    level + (idle - level) * blend
+}
+
+/// Blend each channel toward the average of both by `1 - mix`.
+fn stereo_mix([left, right]: [f32; 2], mix: f32) -> [f32; 2] {
+    let mid = (left + right) / 2.0;
+    [mid + (left - mid) * mix, mid + (right - mid) * mix]
+}
+
+/// Compile `source` into the scene pipeline, reporting problems as errors rather than
+/// letting wgpu panic on them.
+fn build_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    format: wgpu::TextureFormat,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+) -> anyhow::Result<wgpu::RenderPipeline> {
+    check_interface(source)?;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = create_shader(device, source);
+    let pipeline = create_pipeline(device, &shader, format, bind_group_layouts);
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(anyhow::anyhow!("{error}")),
+        None => Ok(pipeline),
+    }
+}
+
+/// Catch shaders written for something else before wgpu does, with a clearer message.
+fn check_interface(source: &str) -> anyhow::Result<()> {
+    for expected in ["fn vs_main", "fn fs_main", "var<uniform>"] {
+        if !source.contains(expected) {
+            anyhow::bail!("shader is missing `{expected}`");
+        }
+    }
+    Ok(())
+}
+
+/// Compile a scene shader, defining the constants it shares with the Rust side.
+fn create_shader(device: &wgpu::Device, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("shader.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(
+            format!("const SPECTRUM_SIZE: u32 = {SPECTRUM_SIZE}u;\n{source}").into(),
+        ),
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Viewport pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
 }
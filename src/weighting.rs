@@ -0,0 +1,109 @@
+use std::f64::consts::PI;
+
+/// Pole frequencies of the A-weighting curve in Hz, from IEC 61672.
+const A_POLES: [f64; 4] = [20.598_997, 107.652_65, 737.862_23, 12_194.217];
+
+/// Second-order IIR section in direct form I.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    /// Digitize the analog section `(b0 s^2 + b1 s + b2) / (s^2 + a1 s + a2)` with the
+    /// bilinear transform.
+    fn bilinear(b: [f64; 3], a: [f64; 2], sample_rate: f64) -> Self {
+        let k = 2.0 * sample_rate;
+        let k2 = k * k;
+        let a0 = k2 + a[0] * k + a[1];
+        Self {
+            b: [
+                (b[0] * k2 + b[1] * k + b[2]) / a0,
+                2.0 * (b[2] - b[0] * k2) / a0,
+                (b[0] * k2 - b[1] * k + b[2]) / a0,
+            ],
+            a: [2.0 * (a[1] - k2) / a0, (k2 - a[0] * k + a[1]) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+
+    /// Magnitude response at `freq` Hz.
+    fn gain(&self, freq: f64, sample_rate: f64) -> f64 {
+        let w = 2.0 * PI * freq / sample_rate;
+        // Evaluate the numerator and denominator at z = e^jw.
+        let eval = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            re.hypot(im)
+        };
+        eval(self.b) / eval([1.0, self.a[0], self.a[1]])
+    }
+
+    fn reset(&mut self) {
+        self.x = [0.0; 2];
+        self.y = [0.0; 2];
+    }
+}
+
+/// A-weighting filter, which rolls off lows and the very top roughly as hearing does at
+/// moderate levels, normalized to unity gain at 1 kHz. One per channel: it keeps state
+/// from one sample to the next.
+///
+/// Digitized with the bilinear transform, so the top end falls off faster than the
+/// analog curve as it nears Nyquist: about 1 dB low at 10 kHz at 48 kHz. That's close
+/// enough for metering.
+#[derive(Debug, Clone)]
+pub struct AWeighting {
+    sections: [Biquad; 3],
+    gain: f64,
+}
+
+impl AWeighting {
+    pub fn new(sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f64;
+        let [w1, w2, w3, w4] = A_POLES.map(|pole| 2.0 * PI * pole);
+        let sections = [
+            // Two zeros at DC and a double pole at 20.6 Hz.
+            Biquad::bilinear([1.0, 0.0, 0.0], [2.0 * w1, w1 * w1], sample_rate),
+            // Two more zeros at DC, poles at 107.7 and 737.9 Hz.
+            Biquad::bilinear([1.0, 0.0, 0.0], [w2 + w3, w2 * w3], sample_rate),
+            // A double pole at 12.2 kHz.
+            Biquad::bilinear([0.0, 0.0, w4 * w4], [2.0 * w4, w4 * w4], sample_rate),
+        ];
+        let at_1k: f64 = sections
+            .iter()
+            .map(|section| section.gain(1000.0, sample_rate))
+            .product();
+
+        Self {
+            sections,
+            gain: 1.0 / at_1k,
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let filtered = self
+            .sections
+            .iter_mut()
+            .fold(sample as f64, |x, section| section.process(x));
+        (filtered * self.gain) as f32
+    }
+
+    /// Forget past samples, e.g. between tracks.
+    pub fn reset(&mut self) {
+        self.sections.iter_mut().for_each(Biquad::reset);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{f32::consts::PI, time::Duration};
+
+use time2freq::{
+    audio::{to_db, AudioPlayer},
+    Analyzer,
+};
+
+fn player(seconds: f32, signal: impl Fn(usize) -> f32) -> AudioPlayer {
+    let frames = (seconds * 48_000.0) as usize;
+    let (mut send, recv) = rtrb::RingBuffer::new(frames * 2);
+    for i in 0..frames {
+        let sample = signal(i);
+        send.push(sample).unwrap();
+        send.push(sample).unwrap();
+    }
+    AudioPlayer::new_analysis(recv, 48_000)
+}
+
+#[test]
+fn sine_rms_is_amplitude_over_root_2() {
+    let mut player = player(1.0, |i| {
+        0.5 * (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin()
+    });
+
+    let frame = player.analyze(Duration::from_millis(500));
+
+    for rms in frame.rms {
+        assert!((rms - 0.5 / 2f32.sqrt()).abs() < 1e-3, "rms {rms}");
+    }
+    assert!(frame.loudness.is_finite(), "loudness {}", frame.loudness);
+    assert_eq!(frame.timestamp, Duration::from_millis(500));
+}
+
+#[test]
+fn noise_loudness_is_finite() {
+    // A small LCG keeps the noise the same from run to run.
+    let mut state = 1u32;
+    let noise: Vec<f32> = (0..48_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+        })
+        .collect();
+    let mut player = player(1.0, |i| 0.25 * noise[i]);
+
+    let frame = player.analyze(Duration::from_millis(500));
+
+    // Uniform noise has an RMS of its peak over root 3.
+    for rms in frame.rms {
+        assert!((rms - 0.25 / 3f32.sqrt()).abs() < 5e-3, "rms {rms}");
+    }
+    assert!(frame.loudness.is_finite(), "loudness {}", frame.loudness);
+    assert!(frame.loudness < 0.0);
+}
+
+#[test]
+fn meters_attack_63_percent_in_one_time_constant() {
+    let mut player = player(1.0, |_| 0.5);
+    let analyzer = player.analyzer_mut();
+    analyzer.set_rms_fast(Duration::from_millis(10), Duration::from_millis(100));
+    analyzer.set_rms_slow(Duration::from_millis(300), Duration::from_millis(300));
+
+    // 10 ms in one frame, then 290 ms more in ten frames, to show frames don't matter.
+    player.analyze(Duration::from_millis(10));
+    let fast = player.analyzer().rms_fast()[0];
+    for _ in 0..10 {
+        player.analyze(Duration::from_millis(29));
+    }
+    let slow = player.analyzer().rms_slow()[0];
+
+    let expected = 0.5 * (1.0 - (-1f32).exp());
+    assert!((fast - expected).abs() < 1e-3, "fast {fast}");
+    assert!((slow - expected).abs() < 1e-3, "slow {slow}");
+    let fast = player.analyzer().rms_fast();
+    assert_eq!(fast[1], fast[0]);
+}
+
+#[test]
+fn analyzer_works_without_a_player() {
+    let mut analyzer = Analyzer::new(48_000);
+    let samples: Vec<f32> = (0..4800)
+        .flat_map(|i| {
+            let sample = 0.5 * (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin();
+            [sample, sample]
+        })
+        .collect();
+
+    analyzer.push_frames(&samples);
+
+    for rms in analyzer.rms() {
+        assert!((rms - 0.5 / 2f32.sqrt()).abs() < 1e-3, "rms {rms}");
+    }
+    assert!(analyzer.loudness().is_finite());
+    assert_eq!(analyzer.timestamp(), Duration::from_millis(100));
+    // 1 kHz falls between bins 42 and 43 of a 2048-point FFT at 48 kHz.
+    let spectrum = analyzer.spectrum(Duration::from_millis(100));
+    let loudest = (0..spectrum.len())
+        .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+        .unwrap();
+    assert!((42..=43).contains(&loudest), "loudest bin {loudest}");
+}
+
+#[test]
+fn fft_size_must_be_a_power_of_two() {
+    let mut analyzer = Analyzer::new(48_000);
+
+    assert!(analyzer.set_fft_size(1000).is_err());
+    assert!(analyzer.set_fft_size(4096).is_ok());
+    assert_eq!(analyzer.fft_size(), 4096);
+}
+
+fn weighted_db(freq: f32) -> f32 {
+    let mut player = player(1.0, |i| 0.5 * (2.0 * PI * freq * i as f32 / 48_000.0).sin());
+    player.analyzer_mut().set_a_weighting(true);
+
+    // Let the filter settle before measuring.
+    player.analyze(Duration::from_millis(500));
+    let frame = player.analyze(Duration::from_millis(100));
+    frame.rms_db[0] - to_db(0.5 / 2f32.sqrt())
+}
+
+#[test]
+fn a_weighting_keeps_1_khz_and_cuts_bass() {
+    let at_1k = weighted_db(1000.0);
+    let at_100 = weighted_db(100.0);
+
+    assert!(at_1k.abs() < 0.1, "1 kHz {at_1k} dB");
+    // IEC 61672 puts 100 Hz at -19.1 dB.
+    assert!((at_100 + 19.1).abs() < 0.5, "100 Hz {at_100} dB");
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use time2freq::AttackRelease;
+
+const FRAME: Duration = Duration::from_millis(10);
+
+fn run(smoother: &mut AttackRelease, target: f32, frames: usize) -> f32 {
+    (0..frames).fold(0.0, |_, _| smoother.process(target, FRAME))
+}
+
+#[test]
+fn rises_with_attack_and_falls_with_release() {
+    let mut smoother = AttackRelease::new(Duration::from_millis(10), Duration::from_millis(250));
+    let step = 1.0 - (-1f32).exp();
+
+    // One time constant covers 63% of the way, in either direction.
+    assert!((run(&mut smoother, 1.0, 1) - step).abs() < 1e-5);
+    run(&mut smoother, 1.0, 100);
+    assert!((smoother.value() - 1.0).abs() < 1e-5);
+    let fallen = run(&mut smoother, 0.0, 25);
+    assert!((fallen - (1.0 - step)).abs() < 1e-4, "fallen to {fallen}");
+}
+
+#[test]
+fn curve_is_independent_of_frame_rate() {
+    let mut coarse = AttackRelease::new(Duration::from_millis(50), Duration::from_millis(200));
+    let mut fine = coarse;
+
+    coarse.process(1.0, Duration::from_millis(40));
+    for _ in 0..40 {
+        fine.process(1.0, Duration::from_millis(1));
+    }
+
+    assert!((coarse.value() - fine.value()).abs() < 1e-5);
+}
+
+#[test]
+fn zero_times_pass_through() {
+    let mut smoother = AttackRelease::new(Duration::ZERO, Duration::ZERO);
+
+    assert_eq!(smoother.process(0.7, FRAME), 0.7);
+    assert_eq!(smoother.process(-0.3, FRAME), -0.3);
+}
@@ -0,0 +1,27 @@
+use time2freq::split_bands;
+
+#[test]
+fn bins_land_in_their_band() {
+    // 100 Hz bins: DC, 100, 200, 300, ..., 4900 Hz.
+    let mut magnitudes = vec![0.0; 50];
+    magnitudes[0] = 10.0;
+    magnitudes[2] = 1.0;
+    magnitudes[3] = 2.0;
+    magnitudes[39] = 3.0;
+    magnitudes[40] = 4.0;
+
+    assert_eq!(
+        split_bands(&magnitudes, 100.0, (250.0, 4000.0)),
+        [1.0, 5.0, 4.0]
+    );
+}
+
+#[test]
+fn crossovers_move_the_split() {
+    let magnitudes = vec![1.0; 11];
+
+    assert_eq!(
+        split_bands(&magnitudes, 100.0, (550.0, 850.0)),
+        [5.0, 3.0, 2.0]
+    );
+}
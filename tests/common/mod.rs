@@ -0,0 +1,34 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// Write a 48 kHz stereo WAV named for `name` to the temp dir, `frames` long, with
+/// `sample(i)` in both channels of frame `i`. Returns its path, for the test to remove.
+pub fn write_wav(name: &str, frames: usize, sample: impl Fn(usize) -> f32) -> PathBuf {
+    write_stereo_wav(name, 48_000, frames, |i| [sample(i); 2])
+}
+
+/// Like `write_wav`, at `sample_rate` and with each side of frame `i` from `sample(i)`.
+/// Samples are written as 32-bit float, so they decode exactly as given.
+pub fn write_stereo_wav(
+    name: &str,
+    sample_rate: u32,
+    frames: usize,
+    sample: impl Fn(usize) -> [f32; 2],
+) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("time2freq-{name}-{}.wav", std::process::id()));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..frames {
+        for side in sample(i) {
+            writer.write_sample(side).unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+    path
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use time2freq::{Command, Controls};
+use winit::event::VirtualKeyCode;
+
+#[test]
+fn default_keymap() {
+    let controls = Controls::default();
+
+    assert_eq!(controls.handle(VirtualKeyCode::Escape), Some(Command::Quit));
+    assert_eq!(
+        controls.handle(VirtualKeyCode::Space),
+        Some(Command::TogglePause)
+    );
+    assert_eq!(
+        controls.handle(VirtualKeyCode::Left),
+        Some(Command::SeekBack(Duration::from_secs(5)))
+    );
+    assert_eq!(controls.handle(VirtualKeyCode::N), Some(Command::NextTrack));
+    assert_eq!(
+        controls.handle(VirtualKeyCode::Z),
+        Some(Command::ToggleFreeze)
+    );
+    assert_eq!(
+        controls.handle(VirtualKeyCode::F12),
+        Some(Command::Screenshot)
+    );
+    assert_eq!(controls.handle(VirtualKeyCode::Q), None);
+}
+
+#[test]
+fn binding_replaces_the_old_command() {
+    let mut controls = Controls::default();
+    controls.bind(VirtualKeyCode::Space, Command::Stop);
+    controls.bind(VirtualKeyCode::Q, Command::Quit);
+    controls.unbind(VirtualKeyCode::Escape);
+
+    assert_eq!(controls.handle(VirtualKeyCode::Space), Some(Command::Stop));
+    assert_eq!(controls.handle(VirtualKeyCode::Q), Some(Command::Quit));
+    assert_eq!(controls.handle(VirtualKeyCode::Escape), None);
+}
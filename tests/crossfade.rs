@@ -0,0 +1,62 @@
+use time2freq::Crossfade;
+
+fn run(crossfade: &mut Crossfade, input: &[f32]) -> Vec<f32> {
+    let mut output = Vec::new();
+    crossfade.process(input, &mut output);
+    output
+}
+
+#[test]
+fn no_fade_passes_straight_through() {
+    let mut crossfade = Crossfade::new(0);
+    assert_eq!(run(&mut crossfade, &[0.1, 0.2, 0.3]), [0.1, 0.2, 0.3]);
+
+    let mut rest = vec![1.0];
+    crossfade.drain(&mut rest);
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn output_is_held_back_by_the_fade_length() {
+    let mut crossfade = Crossfade::new(4);
+    assert!(run(&mut crossfade, &[1.0, 2.0, 3.0]).is_empty());
+    assert_eq!(run(&mut crossfade, &[4.0, 5.0, 6.0]), [1.0, 2.0]);
+
+    let mut rest = Vec::new();
+    crossfade.drain(&mut rest);
+    assert_eq!(rest, [3.0, 4.0, 5.0, 6.0]);
+}
+
+#[test]
+fn tail_fades_under_the_next_head() {
+    let mut crossfade = Crossfade::new(100);
+    assert!(run(&mut crossfade, &[1.0; 100]).is_empty());
+    crossfade.start_fade();
+
+    // The next track is silent, leaving just the outgoing gain curve.
+    let mut faded = run(&mut crossfade, &[0.0; 200]);
+    let mut rest = Vec::new();
+    crossfade.drain(&mut rest);
+    faded.extend(rest);
+
+    assert_eq!(faded.len(), 200);
+    assert_eq!(faded[0], 1.0);
+    assert!(faded[..100].windows(2).all(|pair| pair[1] <= pair[0]));
+    assert!((faded[50] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    assert!(faded[100..].iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn tail_that_runs_out_of_track_keeps_fading() {
+    let mut crossfade = Crossfade::new(10);
+    run(&mut crossfade, &[1.0; 10]);
+    crossfade.start_fade();
+    run(&mut crossfade, &[0.0; 4]);
+
+    let mut rest = Vec::new();
+    crossfade.drain(&mut rest);
+
+    assert_eq!(rest.len(), 10);
+    assert!(rest[..4].iter().all(|sample| *sample > 0.0));
+    assert!(rest[4..].windows(2).all(|pair| pair[1] < pair[0]));
+}
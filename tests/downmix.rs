@@ -0,0 +1,58 @@
+use time2freq::audio::{downmix, upmix, Downmix};
+
+#[test]
+fn mono_is_copied_to_both_sides() {
+    assert_eq!(downmix(&[0.5]), (0.5, 0.5));
+}
+
+#[test]
+fn stereo_passes_through() {
+    assert_eq!(downmix(&[0.5, -0.25]), (0.5, -0.25));
+}
+
+#[test]
+fn empty_frame_is_silence() {
+    assert_eq!(downmix(&[]), (0.0, 0.0));
+}
+
+#[test]
+fn surround_skips_lfe_and_shares_center() {
+    // FL, FR, C, LFE, SL, SR
+    let (left, right) = downmix(&[0.5, 0.25, 0.25, 1.0, 0.0, 0.625]);
+
+    assert_eq!(left, 0.25);
+    assert_eq!(right, 0.375);
+}
+
+#[test]
+fn upmixed_frames_fold_back_to_the_same_pair() {
+    for channels in 1..=8 {
+        let mut frame = vec![0.0; channels];
+        upmix(&mut frame, 0.5, 0.5);
+
+        assert_eq!(downmix(&frame), (0.5, 0.5), "{channels} channels");
+    }
+}
+
+#[test]
+fn mono_averages_both_sides() {
+    assert_eq!(Downmix::Mono.apply(&[0.5, -0.25]), (0.125, 0.125));
+    assert_eq!(Downmix::Stereo.apply(&[0.5, -0.25]), (0.5, -0.25));
+}
+
+#[test]
+fn itu_folds_center_and_surrounds_at_minus_3_db() {
+    // FL, FR, C, LFE, SL, SR
+    let (left, right) = Downmix::itu_5_1().apply(&[0.5, 0.0, 0.5, 1.0, 0.0, 0.5]);
+
+    let g = std::f32::consts::FRAC_1_SQRT_2;
+    assert_eq!(left, 0.5 + 0.5 * g);
+    assert_eq!(right, 0.5 * g + 0.5 * g);
+}
+
+#[test]
+fn custom_leaves_out_unlisted_channels() {
+    let downmix = Downmix::Custom(vec![[1.0, 0.0], [0.0, 0.5]]);
+
+    assert_eq!(downmix.apply(&[0.5, 0.5, 1.0]), (0.5, 0.25));
+}
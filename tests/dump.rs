@@ -0,0 +1,102 @@
+use time2freq::{AudioFile, CopyMethod};
+
+mod common;
+
+use common::write_stereo_wav;
+
+#[test]
+fn dump_splits_channels() {
+    let path = write_stereo_wav("dump", 44_100, 10_000, |i| [i as f32 / 10_000.0, -0.5]);
+
+    let channels = AudioFile::open(&path).unwrap().dump();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(channels.len(), 2);
+    assert_eq!(channels[0].len(), 10_000);
+    assert_eq!(channels[1].len(), 10_000);
+    assert_eq!(channels[0][5_000], 0.5);
+    assert!(channels[1].iter().all(|&sample| sample == -0.5));
+}
+
+#[test]
+fn truncated_file_ends_cleanly() {
+    let path = write_stereo_wav("truncated", 44_100, 20_000, |_| [0.03, -0.03]);
+
+    // Cut the data off mid-frame, past what the header promises.
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    let len = file.metadata().unwrap().len();
+    file.set_len(len - 4003).unwrap();
+
+    let mut audio = AudioFile::open(&path).unwrap();
+    let mut frames = 0;
+    let end = loop {
+        match audio.next_sample(CopyMethod::Interleaved) {
+            Ok(Some(buf)) => frames += buf.samples().len() / 2,
+            other => break other.map(|_| ()),
+        }
+    };
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(end.is_ok(), "expected end of stream, got {end:?}");
+    assert!(frames > 0 && frames < 20_000);
+}
+
+/// A mono IMA ADPCM WAV of `blocks` 256-byte blocks, 505 frames each, with the block at
+/// `bad_block` given an out-of-range step index so it fails to decode.
+fn adpcm_wav(blocks: usize, bad_block: usize) -> Vec<u8> {
+    const BLOCK_ALIGN: usize = 256;
+    const FRAMES_PER_BLOCK: usize = (BLOCK_ALIGN - 4) * 2 + 1;
+    let data_len = blocks * BLOCK_ALIGN;
+
+    let mut wav = Vec::new();
+    wav.extend(b"RIFF");
+    wav.extend((4 + 28 + 12 + 8 + data_len as u32).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(20u32.to_le_bytes());
+    wav.extend(0x11u16.to_le_bytes()); // IMA ADPCM
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(44_100u32.to_le_bytes());
+    wav.extend(((44_100 * BLOCK_ALIGN / FRAMES_PER_BLOCK) as u32).to_le_bytes());
+    wav.extend((BLOCK_ALIGN as u16).to_le_bytes());
+    wav.extend(4u16.to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend((FRAMES_PER_BLOCK as u16).to_le_bytes());
+    wav.extend(b"fact");
+    wav.extend(4u32.to_le_bytes());
+    wav.extend(((blocks * FRAMES_PER_BLOCK) as u32).to_le_bytes());
+    wav.extend(b"data");
+    wav.extend((data_len as u32).to_le_bytes());
+    for block in 0..blocks {
+        // Predictor, step index, and a reserved byte, then two samples a byte.
+        let step_index = if block == bad_block { 0xff } else { 0 };
+        wav.extend([0, 0, step_index, 0]);
+        wav.extend([0x17; BLOCK_ALIGN - 4]);
+    }
+    wav
+}
+
+#[test]
+fn corrupt_packet_is_skipped() {
+    let path = std::env::temp_dir().join(format!("time2freq-corrupt-{}.wav", std::process::id()));
+    let blocks = 100;
+    std::fs::write(&path, adpcm_wav(blocks, blocks / 2)).unwrap();
+
+    let mut audio = AudioFile::open(&path).unwrap();
+    let mut frames = 0;
+    let end = loop {
+        match audio.next_sample(CopyMethod::Interleaved) {
+            Ok(Some(buf)) => frames += buf.samples().len(),
+            other => break other.map(|_| ()),
+        }
+    };
+    std::fs::remove_file(&path).unwrap();
+
+    let total = blocks * 505;
+    assert!(end.is_ok(), "expected end of stream, got {end:?}");
+    // Only the packet holding the bad block is lost; decoding goes on past it.
+    assert!(frames < total, "nothing skipped");
+    assert!(
+        frames > total / 2 + 505,
+        "stopped at the bad packet, {frames} frames"
+    );
+}
@@ -0,0 +1,78 @@
+use std::{f32::consts::PI, time::Duration};
+
+use time2freq::audio::{rms, to_db, AnalysisFrame, SILENCE_DB, VISUAL_LOUDNESS_FLOOR_LUFS};
+
+fn sine(amplitude: f32, len: usize) -> Vec<f32> {
+    // 1 kHz at 48 kHz, a whole number of cycles.
+    (0..len)
+        .map(|i| amplitude * (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin())
+        .collect()
+}
+
+#[test]
+fn full_scale_sine_is_minus_3_db() {
+    let samples = sine(1.0, 4800);
+
+    assert!((rms(&samples) - 0.5f32.sqrt()).abs() < 1e-4);
+    assert!((to_db(rms(&samples)) + 3.01).abs() < 0.01);
+}
+
+#[test]
+fn half_scale_sine_is_6_db_lower() {
+    let full = to_db(rms(&sine(1.0, 4800)));
+    let half = to_db(rms(&sine(0.5, 4800)));
+
+    assert!((full - half - 6.02).abs() < 0.01);
+}
+
+#[test]
+fn silence_hits_the_floor() {
+    assert_eq!(rms(&[0.0; 64]), 0.0);
+    assert_eq!(rms(&[]), 0.0);
+    assert_eq!(to_db(0.0), SILENCE_DB);
+}
+
+fn frame(db: f32, loudness: f32) -> AnalysisFrame {
+    let amplitude = 10f32.powf(db / 20.0);
+    AnalysisFrame {
+        rms: [amplitude; 2],
+        rms_db: [to_db(amplitude); 2],
+        peak: [amplitude; 2],
+        true_peak: [amplitude; 2],
+        loudness,
+        timestamp: Duration::ZERO,
+    }
+}
+
+#[test]
+fn visual_levels_span_minus_20_db_to_full_scale() {
+    assert_eq!(frame(0.0, 0.0).visual_levels().0, [1.0; 2]);
+    assert!((frame(-10.0, 0.0).visual_levels().0[0]).abs() < 1e-5);
+    assert_eq!(frame(-20.0, 0.0).visual_levels().0, [-1.0; 2]);
+    assert_eq!(frame(-20.0, 0.0).visual_peak(), [-1.0; 2]);
+}
+
+#[test]
+fn visual_levels_of_silence_sit_at_the_bottom() {
+    let (rms, loudness) = frame(SILENCE_DB, f32::NEG_INFINITY).visual_levels();
+
+    assert_eq!(rms, [-1.0; 2]);
+    assert_eq!(loudness, -1.0);
+}
+
+#[test]
+fn visual_levels_clamp_at_both_ends() {
+    let (quiet_rms, quiet_loudness) = frame(-20.0, -20.0).visual_levels();
+    let (full_rms, full_loudness) = frame(0.0, 0.0).visual_levels();
+    let (hot_rms, hot_loudness) = frame(6.0, 3.0).visual_levels();
+
+    assert_eq!(quiet_rms, [-1.0; 2]);
+    assert!(quiet_loudness.abs() < 1e-5, "loudness {quiet_loudness}");
+    assert_eq!((full_rms, full_loudness), ([1.0; 2], 1.0));
+    assert_eq!((hot_rms, hot_loudness), ([1.0; 2], 1.0));
+    assert_eq!(
+        frame(-40.0, VISUAL_LOUDNESS_FLOOR_LUFS).visual_levels(),
+        ([-1.0; 2], -1.0)
+    );
+    assert_eq!(frame(-60.0, -60.0).visual_levels(), ([-1.0; 2], -1.0));
+}
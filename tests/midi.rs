@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use time2freq::{midi::MidiMapping, VizParam};
+
+const CLOCK: [u8; 1] = [0xf8];
+
+/// Clock ticks at `bpm`, as `(tick, params)` for the ticks that set anything.
+fn clock(mapping: &mut MidiMapping, bpm: f32, ticks: u32) -> Vec<(u32, Vec<VizParam>)> {
+    let tick = Duration::from_secs_f32(60.0 / bpm / 24.0);
+    (0..ticks)
+        .map(|i| (i, mapping.translate(&CLOCK, tick * i)))
+        .filter(|(_, params)| !params.is_empty())
+        .collect()
+}
+
+#[test]
+fn clock_beats_every_24_ticks_with_the_tempo() {
+    let mut mapping = MidiMapping::new(Vec::new());
+    mapping.translate(&[0xfa], Duration::ZERO);
+
+    let beats = clock(&mut mapping, 120.0, 49);
+
+    assert_eq!(
+        beats.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+        [0, 24, 48]
+    );
+    assert_eq!(beats[0].1, [VizParam::Beat]);
+    for (_, params) in &beats[1..] {
+        let [VizParam::Bpm(bpm), VizParam::Beat] = params[..] else {
+            panic!("{params:?}");
+        };
+        assert!((bpm - 120.0).abs() < 0.01, "bpm {bpm}");
+    }
+}
+
+#[test]
+fn stop_clears_the_tempo() {
+    let mut mapping = MidiMapping::new(Vec::new());
+    clock(&mut mapping, 100.0, 30);
+
+    assert_eq!(
+        mapping.translate(&[0xfc], Duration::from_secs(1)),
+        [VizParam::Bpm(0.0)]
+    );
+}
+
+#[test]
+fn control_changes_set_mapped_params() {
+    let mut mapping = MidiMapping::new(vec![(1, "stereo_mix".into()), (7, "volume".into())]);
+
+    assert_eq!(
+        mapping.translate(&[0xb3, 1, 127], Duration::ZERO),
+        [VizParam::StereoMix(1.0)]
+    );
+    assert_eq!(mapping.translate(&[0xb0, 2, 64], Duration::ZERO), []);
+    // Note on, not a control change.
+    assert_eq!(mapping.translate(&[0x90, 1, 64], Duration::ZERO), []);
+}
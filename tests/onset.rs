@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use time2freq::OnsetDetector;
+
+const FRAME: Duration = Duration::from_millis(16);
+
+fn burst() -> Vec<f32> {
+    let mut magnitudes = vec![0.0; 64];
+    magnitudes[8..16].fill(1.0);
+    magnitudes
+}
+
+#[test]
+fn steady_spectrum_has_no_onsets() {
+    let mut detector = OnsetDetector::default();
+    let magnitudes = burst();
+
+    for _ in 0..60 {
+        assert_eq!(detector.process(&magnitudes, FRAME), 0.0);
+    }
+}
+
+#[test]
+fn burst_after_silence_is_an_onset() {
+    let mut detector = OnsetDetector::default();
+    let silence = vec![0.0; 64];
+
+    for _ in 0..60 {
+        detector.process(&silence, FRAME);
+    }
+
+    assert_eq!(detector.process(&burst(), FRAME), 1.0);
+}
+
+#[test]
+fn constant_flux_stops_standing_out() {
+    let mut detector = OnsetDetector::default();
+    let silence = vec![0.0; 64];
+    let burst = burst();
+
+    // Bursts every other frame for a few seconds raise the threshold past their own flux.
+    for _ in 0..200 {
+        detector.process(&silence, FRAME);
+        detector.process(&burst, FRAME);
+    }
+    detector.process(&silence, FRAME);
+
+    assert_eq!(detector.process(&burst, FRAME), 0.0);
+}
@@ -0,0 +1,110 @@
+use time2freq::{AudioFile, Tags, Time2FreqError};
+
+mod common;
+
+use common::write_wav;
+
+#[test]
+fn text_file_is_a_clean_error() {
+    let path = std::env::temp_dir().join(format!("time2freq-not-audio-{}.txt", std::process::id()));
+    std::fs::write(&path, "this is not an audio file\n".repeat(64)).unwrap();
+
+    let result = AudioFile::open(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(result, Err(Time2FreqError::UnsupportedFormat(_))));
+}
+
+#[test]
+fn missing_file_is_a_clean_error() {
+    let result = AudioFile::open("/nonexistent/time2freq/song.flac");
+    assert!(matches!(result, Err(Time2FreqError::Io(_))));
+}
+
+#[test]
+fn untagged_file_has_no_tags() {
+    let path = write_wav("untagged", 1024, |_| 0.0);
+
+    let audio = AudioFile::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(audio.tags(), &Tags::default());
+    assert!(!audio.info().contains_key("title"));
+}
+
+#[test]
+fn url_streams_over_http() {
+    let path = write_wav("served", 48_000, |_| 0.0);
+    let body = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/song.wav", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        use std::io::{BufRead, BufReader, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        // Read the request up to the blank line that ends its headers.
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    });
+
+    let mut audio = AudioFile::open(&url).unwrap();
+    let frames = audio.dump()[0].len();
+    server.join().unwrap();
+
+    assert_eq!(audio.sample_rate(), 48_000);
+    assert_eq!(audio.channels(), 2);
+    assert_eq!(frames, 48_000);
+}
+
+#[test]
+fn unreachable_url_is_a_clean_error() {
+    // Nothing listens on port 1.
+    let result = AudioFile::open("http://127.0.0.1:1/song.mp3");
+    assert!(matches!(result, Err(Time2FreqError::Http(_))));
+}
+
+#[test]
+fn stalled_stream_gives_way_to_cancel() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/song.wav", listener.local_addr().unwrap());
+    let (done, wait) = std::sync::mpsc::channel::<()>();
+    let server = std::thread::spawn(move || {
+        use std::io::{BufRead, BufReader, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        // Promise a body and never send it, holding the connection open.
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: 1000000\r\n\r\n"
+        )
+        .unwrap();
+        let _ = wait.recv();
+    });
+
+    let started = std::time::Instant::now();
+    let result = AudioFile::open_cancellable(&url, || true);
+    let elapsed = started.elapsed();
+    done.send(()).unwrap();
+    server.join().unwrap();
+
+    assert!(result.is_err());
+    assert!(
+        elapsed < std::time::Duration::from_secs(2),
+        "took {elapsed:?}"
+    );
+}
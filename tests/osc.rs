@@ -0,0 +1,55 @@
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use time2freq::{osc, VizParam};
+
+fn message(addr: &str, args: Vec<OscType>) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args,
+    })
+}
+
+#[test]
+fn messages_set_params() {
+    let packet = message("/viz/stereo_mix", vec![OscType::Double(0.25)]);
+
+    assert_eq!(osc::params(&packet), [Ok(VizParam::StereoMix(0.25))]);
+}
+
+#[test]
+fn palette_stop_takes_rgb() {
+    let packet = message(
+        "/viz/palette/1",
+        vec![OscType::Float(1.0), OscType::Float(0.5), OscType::Int(0)],
+    );
+
+    let color = |channel, value| {
+        Ok(VizParam::PaletteColor {
+            stop: 1,
+            channel,
+            value,
+        })
+    };
+    assert_eq!(
+        osc::params(&packet),
+        [color(0, 1.0), color(1, 0.5), color(2, 0.0)]
+    );
+}
+
+#[test]
+fn bundles_apply_every_message() {
+    let packet = OscPacket::Bundle(OscBundle {
+        timetag: OscTime::from((0, 1)),
+        content: vec![
+            message("/viz/volume", vec![OscType::Float(0.5)]),
+            message("/viz/brightness", vec![OscType::Float(0.5)]),
+            message("/viz/user/0", vec![OscType::String("x".into())]),
+            message("/other/volume", vec![OscType::Float(0.5)]),
+            message("/viz/palette", vec![]),
+        ],
+    });
+
+    let params = osc::params(&packet);
+    assert_eq!(params[0], Ok(VizParam::Volume(0.5)));
+    assert_eq!(params.len(), 5);
+    assert!(params[1..].iter().all(Result::is_err), "{params:?}");
+}
@@ -0,0 +1,128 @@
+use time2freq::audio::{
+    convert_output, fill_output, is_underrun, mute_while_scrubbing, AudioPlayer, ResampleQuality,
+};
+
+#[test]
+fn underrun_writes_silence_for_any_channel_count() {
+    for channels in 1..=8 {
+        let (_, mut source) = rtrb::RingBuffer::<f32>::new(16);
+        let mut data = vec![f32::NAN; channels * 32];
+
+        assert_eq!(fill_output(&mut data, channels, &mut source), 0);
+        assert!(
+            data.iter().all(|sample| *sample == 0.0),
+            "{channels} channels"
+        );
+    }
+}
+
+#[test]
+fn stereo_input_is_copied_through() {
+    let (mut sink, mut source) = rtrb::RingBuffer::<f32>::new(16);
+    for sample in [0.1, -0.1, 0.2, -0.2] {
+        sink.push(sample).unwrap();
+    }
+    let mut data = vec![f32::NAN; 4];
+
+    assert_eq!(fill_output(&mut data, 2, &mut source), 2);
+    assert_eq!(data, [0.1, -0.1, 0.2, -0.2]);
+}
+
+fn filled(channels: usize, input: &[f32], frames: usize) -> (usize, Vec<f32>) {
+    let (mut sink, mut source) = rtrb::RingBuffer::<f32>::new(16);
+    for sample in input {
+        sink.push(*sample).unwrap();
+    }
+    let mut data = vec![f32::NAN; channels * frames];
+    let filled = fill_output(&mut data, channels, &mut source);
+    (filled, data)
+}
+
+#[test]
+fn mono_device_gets_the_average() {
+    let (filled, data) = filled(1, &[0.5, 0.25, -0.5, -0.25], 3);
+
+    assert_eq!(filled, 2);
+    assert_eq!(data, [0.375, -0.375, 0.0]);
+}
+
+#[test]
+fn surround_device_keeps_frame_stride() {
+    let (filled, data) = filled(6, &[0.5, 0.25, -0.5, -0.25], 3);
+
+    assert_eq!(filled, 2);
+    assert_eq!(data[..6], [0.5, 0.25, 0.375, 0.0, 0.5, 0.25]);
+    assert_eq!(data[6..12], [-0.5, -0.25, -0.375, 0.0, -0.5, -0.25]);
+    assert!(data[12..].iter().all(|sample| *sample == 0.0));
+}
+
+const MIX: [f32; 3] = [-1.0, 0.0, 0.5];
+
+#[test]
+fn f32_output_is_unchanged() {
+    let mut output = [f32::NAN; 3];
+    convert_output(&mut output, &MIX);
+    assert_eq!(output, MIX);
+}
+
+#[test]
+fn i8_output_spans_the_type() {
+    let mut output = [1i8; 3];
+    convert_output(&mut output, &MIX);
+    assert_eq!(output, [i8::MIN, 0, 64]);
+}
+
+#[test]
+fn i16_output_spans_the_type() {
+    let mut output = [1i16; 3];
+    convert_output(&mut output, &MIX);
+    assert_eq!(output, [i16::MIN, 0, 16_384]);
+}
+
+#[test]
+fn u16_output_is_centered_on_the_midpoint() {
+    let mut output = [1u16; 3];
+    convert_output(&mut output, &MIX);
+    assert_eq!(output, [0, 32_768, 49_152]);
+}
+
+#[test]
+fn i32_output_spans_the_type() {
+    let mut output = [1i32; 3];
+    convert_output(&mut output, &MIX);
+    assert_eq!(output, [i32::MIN, 0, 1 << 30]);
+}
+
+#[test]
+fn running_dry_is_an_underrun_only_mid_track() {
+    assert!(is_underrun(10, 32, true));
+    assert!(!is_underrun(32, 32, true));
+    // Before the first track, after the last, or after a stop.
+    assert!(!is_underrun(0, 32, false));
+}
+
+#[test]
+fn idle_player_reports_no_underruns() {
+    let player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    assert_eq!(player.underruns(), 0);
+}
+
+#[test]
+fn stop_ends_scrubbing() {
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player.set_scrubbing(true);
+    player.stop();
+
+    let (mut sink, mut source) = rtrb::RingBuffer::<f32>::new(64);
+    for _ in 0..64 {
+        sink.push(0.5).unwrap();
+    }
+    let mut data = vec![0.0; 64];
+    fill_output(&mut data, 2, &mut source);
+    mute_while_scrubbing(&mut data, player.is_scrubbing());
+
+    assert!(!player.is_scrubbing());
+    assert!(data.iter().all(|sample| *sample == 0.5));
+}
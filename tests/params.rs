@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use time2freq::{VizParam, PALETTE_PRESETS};
+
+#[test]
+fn parses_names_and_values() {
+    assert_eq!("stereo_mix 0.5".parse(), Ok(VizParam::StereoMix(0.5)));
+    assert_eq!(
+        VizParam::parse("palette/2/g", 0.25),
+        Ok(VizParam::PaletteColor {
+            stop: 2,
+            channel: 1,
+            value: 0.25
+        })
+    );
+    assert_eq!(VizParam::parse("user/3", -2.0), Ok(VizParam::User(3, -2.0)));
+    assert_eq!(
+        VizParam::parse("loudness_release", 250.0),
+        Ok(VizParam::LoudnessRelease(Duration::from_millis(250)))
+    );
+}
+
+#[test]
+fn clamps_to_range() {
+    assert_eq!(
+        VizParam::parse("stereo_mix", 3.0),
+        Ok(VizParam::StereoMix(1.0))
+    );
+    assert_eq!(VizParam::parse("volume", -1.0), Ok(VizParam::Volume(0.0)));
+    assert_eq!(
+        VizParam::parse("palette", 99.0),
+        Ok(VizParam::Palette(PALETTE_PRESETS.len() - 1))
+    );
+}
+
+#[test]
+fn rejects_unknown_and_malformed_params() {
+    assert!(VizParam::parse("brightness", 1.0).is_err());
+    assert!(VizParam::parse("volume/1", 1.0).is_err());
+    assert!(VizParam::parse("palette/3/r", 1.0).is_err());
+    assert!(VizParam::parse("palette/0/a", 1.0).is_err());
+    assert!(VizParam::parse("user/4", 1.0).is_err());
+    assert!(VizParam::parse("volume", f32::NAN).is_err());
+    assert!("volume".parse::<VizParam>().is_err());
+    assert!("volume loud".parse::<VizParam>().is_err());
+}
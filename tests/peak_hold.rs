@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use time2freq::PeakHold;
+
+const FRAME: Duration = Duration::from_millis(100);
+
+#[test]
+fn holds_then_decays() {
+    let mut peak = PeakHold::new(Duration::from_millis(250), 1.0);
+
+    assert_eq!(peak.process(0.8, FRAME), 0.8);
+    assert_eq!(peak.process(0.2, FRAME), 0.8);
+    assert_eq!(peak.process(0.2, FRAME), 0.8);
+
+    // Past the hold time it falls by `decay * dt` per reading.
+    assert!((peak.process(0.2, FRAME) - 0.7).abs() < 1e-6);
+    assert!((peak.process(0.2, FRAME) - 0.6).abs() < 1e-6);
+}
+
+#[test]
+fn never_falls_below_the_input() {
+    let mut peak = PeakHold::new(Duration::ZERO, 10.0);
+
+    peak.process(0.8, FRAME);
+    assert_eq!(peak.process(0.5, FRAME), 0.5);
+}
+
+#[test]
+fn new_peak_restarts_the_hold() {
+    let mut peak = PeakHold::new(Duration::from_millis(150), 1.0);
+
+    peak.process(0.5, FRAME);
+    peak.process(0.1, FRAME);
+    assert_eq!(peak.process(0.9, FRAME), 0.9);
+    assert_eq!(peak.process(0.1, FRAME), 0.9);
+}
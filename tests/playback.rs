@@ -0,0 +1,286 @@
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+use time2freq::audio::{AudioPlayer, PlaybackEvent, ResampleQuality};
+
+mod common;
+
+use common::write_wav;
+
+#[test]
+fn looping_restarts_the_track() {
+    let input = write_wav("loop-in", 4800, |i| (i % 100) as f32 / 500.0 - 0.1);
+    let output =
+        std::env::temp_dir().join(format!("time2freq-loop-out-{}.wav", std::process::id()));
+
+    let mut player =
+        AudioPlayer::new_wav(&output, 48_000, 20, 1024, ResampleQuality::Fast).unwrap();
+    player.set_loop(true);
+    player.play(input.clone());
+
+    let started = Instant::now();
+    let mut starts = 0;
+    let mut finished = false;
+    while starts < 3 && started.elapsed() < Duration::from_secs(10) {
+        while let Some(event) = player.poll_event() {
+            match event {
+                PlaybackEvent::TrackStarted(_) => starts += 1,
+                PlaybackEvent::Finished => finished = true,
+                _ => (),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    drop(player);
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    assert_eq!(starts, 3, "track didn't loop");
+    assert!(!finished);
+}
+
+#[test]
+fn headless_analyzes_every_frame() {
+    let input = write_wav("headless", 48_000, |i| {
+        (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin()
+    });
+
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player.play(input.clone());
+
+    let started = Instant::now();
+    let mut ended = false;
+    let mut last = None;
+    while started.elapsed() < Duration::from_secs(10) {
+        while let Some(event) = player.poll_event() {
+            ended |= matches!(event, PlaybackEvent::TrackEnded(_));
+        }
+        if ended && player.buffered_analysis_frames() == 0 {
+            break;
+        }
+        if ended || player.buffered_analysis_frames() >= 4800 {
+            last = Some(player.analyze(Duration::from_millis(100)));
+        } else {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    let tail = player.analyze(Duration::from_millis(100));
+    std::fs::remove_file(&input).unwrap();
+
+    assert!(ended, "track didn't end");
+    assert_eq!(tail.timestamp, Duration::from_secs(1));
+    let rms = last.unwrap().rms[0];
+    assert!((rms - 0.5f32.sqrt()).abs() < 1e-3, "rms {rms}");
+}
+
+#[test]
+fn stop_goes_idle_until_the_next_play() {
+    let input = write_wav("stop", 48_000, |i| (i % 100) as f32 / 500.0 - 0.1);
+
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player.play(input.clone());
+    player.enqueue(input.clone());
+
+    // Wait for the track to get going, then stop it partway.
+    let started = Instant::now();
+    while player.buffered_analysis_frames() < 4800 && started.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(player.analyze(Duration::from_millis(100)).rms[0] > 0.0);
+    player.stop();
+
+    let mut events = Vec::new();
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(500) {
+        events.extend(std::iter::from_fn(|| player.poll_event()));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let frame = player.analyze(Duration::from_millis(100));
+    let idle_frames = player.buffered_analysis_frames();
+
+    assert!(
+        matches!(
+            events[..],
+            [
+                PlaybackEvent::TrackStarted(_),
+                PlaybackEvent::TrackInfo(..),
+                PlaybackEvent::TrackEnded(_)
+            ]
+        ),
+        "{events:?}"
+    );
+    assert_eq!(frame.rms, [0.0; 2]);
+    assert_eq!(idle_frames, 0);
+    assert_eq!(player.position(), Duration::ZERO);
+    assert_eq!(player.duration(), None);
+
+    player.play(input.clone());
+    let mut restarted = false;
+    let started = Instant::now();
+    while !restarted && started.elapsed() < Duration::from_secs(10) {
+        restarted = std::iter::from_fn(|| player.poll_event())
+            .any(|event| matches!(event, PlaybackEvent::TrackStarted(_)));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    drop(player);
+    std::fs::remove_file(&input).unwrap();
+
+    assert!(restarted, "play after stop didn't start");
+}
+
+#[test]
+fn silence_analyzes_to_finite_levels() {
+    let input = write_wav("silence", 9600, |_| 0.0);
+
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player.play(input.clone());
+
+    let started = Instant::now();
+    while player.buffered_analysis_frames() < 4800 && started.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let frame = player.analyze(Duration::from_millis(100));
+    drop(player);
+    std::fs::remove_file(&input).unwrap();
+
+    let (rms, loudness) = frame.visual_levels();
+    let levels = [frame.rms_db, frame.peak, rms, frame.visual_peak()];
+    assert!(
+        levels.iter().flatten().all(|level| level.is_finite()),
+        "{levels:?}"
+    );
+    assert!(frame.loudness.is_finite(), "loudness {}", frame.loudness);
+    assert!(loudness.is_finite(), "visual loudness {loudness}");
+    assert_eq!(rms, [-1.0; 2]);
+}
+
+#[test]
+fn levels_cover_the_analysis_window_at_any_frame_rate() {
+    // 10 ms at half scale, then 10 ms of silence, over and over.
+    let input = write_wav(
+        "window",
+        48_000,
+        |i| {
+            if (i / 480) % 2 == 0 {
+                0.5
+            } else {
+                0.0
+            }
+        },
+    );
+
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player
+        .analyzer_mut()
+        .set_analysis_window(Duration::from_millis(50));
+    player.play(input.clone());
+
+    let started = Instant::now();
+    while player.buffered_analysis_frames() < 4800 && started.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    player.analyze(Duration::from_millis(50));
+    // At 1000 fps each frame is only 1 ms, all loud or all silent.
+    let levels: Vec<f32> = (0..50)
+        .map(|_| player.analyze(Duration::from_millis(1)).rms[0])
+        .collect();
+    drop(player);
+    std::fs::remove_file(&input).unwrap();
+
+    // 50 ms holds two or three of the loud stretches.
+    let (low, high) = ((0.4f32 * 0.25).sqrt(), (0.6f32 * 0.25).sqrt());
+    assert!(
+        levels
+            .iter()
+            .all(|rms| (low - 1e-3..=high + 1e-3).contains(rms)),
+        "{levels:?}"
+    );
+}
+
+#[test]
+fn stalled_analysis_catches_up() {
+    let input = write_wav("stall-in", 2 * 48_000, |i| (i % 100) as f32 / 500.0 - 0.1);
+    let output =
+        std::env::temp_dir().join(format!("time2freq-stall-out-{}.wav", std::process::id()));
+
+    let mut player =
+        AudioPlayer::new_wav(&output, 48_000, 200, 1024, ResampleQuality::Fast).unwrap();
+    player.play(input.clone());
+
+    // Stall well past one analysis window.
+    std::thread::sleep(Duration::from_millis(500));
+    let behind = player.buffered_analysis_frames();
+    player.analyze(Duration::from_millis(10));
+    let after = player.buffered_analysis_frames();
+    let dropped = player.dropped_samples();
+    drop(player);
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    assert!(behind > 9600, "only {behind} frames behind");
+    // The 10 ms analyzed plus one 50 ms window, and a little the decoder added since.
+    assert!(after < 4800, "still {after} frames behind");
+    assert!(dropped > 0);
+}
+
+fn wav_error(latency_ms: usize, chunk_size: usize) -> String {
+    let output = std::env::temp_dir().join(format!("time2freq-invalid-{}.wav", std::process::id()));
+    let result = AudioPlayer::new_wav(
+        &output,
+        48_000,
+        latency_ms,
+        chunk_size,
+        ResampleQuality::Fast,
+    );
+    let _ = std::fs::remove_file(&output);
+    match result {
+        Ok(_) => panic!("accepted latency {latency_ms} ms, chunk size {chunk_size}"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn zero_chunk_size_is_rejected() {
+    assert_eq!(wav_error(100, 0), "chunk_size must be > 0");
+}
+
+#[test]
+fn zero_latency_is_rejected() {
+    assert!(wav_error(0, 1024).starts_with("latency_ms too small for sample rate"));
+}
+
+#[test]
+fn chunk_must_fit_in_the_buffer() {
+    // 10 ms at 48 kHz, doubled, is 960 frames.
+    assert!(wav_error(10, 961).starts_with("chunk_size 961 doesn't fit"));
+
+    let output = std::env::temp_dir().join(format!("time2freq-fits-{}.wav", std::process::id()));
+    let result = AudioPlayer::new_wav(&output, 48_000, 10, 960, ResampleQuality::Fast);
+    let fits = result.is_ok();
+    drop(result);
+    std::fs::remove_file(&output).unwrap();
+    assert!(fits);
+}
+
+#[test]
+fn failing_last_file_still_finishes() {
+    let mut player = AudioPlayer::new_headless(48_000, 1024, ResampleQuality::Fast).unwrap();
+    player.play("/nonexistent/time2freq/song.flac".into());
+
+    let mut events = Vec::new();
+    let started = Instant::now();
+    let finished =
+        |events: &[PlaybackEvent]| matches!(events.last(), Some(PlaybackEvent::Finished));
+    while !finished(&events) && started.elapsed() < Duration::from_secs(5) {
+        events.extend(std::iter::from_fn(|| player.poll_event()));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(
+        matches!(events[0], PlaybackEvent::LoadFailed(..)),
+        "{events:?}"
+    );
+    assert!(finished(&events), "{events:?}");
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use time2freq::Preset;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("time2freq-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn full_preset_parses() {
+    let preset = Preset::parse(
+        r#"
+        shader_source = "// WGSL"
+        palette = [[0, 0, 0.2], [0, 0.8, 0.6], [1, 1, 0.8]]
+        background = [0.02, 0.02, 0.05]
+        loudness_smoothing_ms = [10, 400]
+        band_smoothing_ms = 80
+        crossovers = [200, 3000]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(preset.shader_source.as_deref(), Some("// WGSL"));
+    assert_eq!(preset.palette.unwrap()[1], [0.0, 0.8, 0.6]);
+    assert_eq!(preset.loudness_smoothing_ms, Some([10.0, 400.0]));
+    assert_eq!(preset.crossovers, Some([200.0, 3000.0]));
+}
+
+#[test]
+fn empty_preset_changes_nothing() {
+    assert_eq!(Preset::parse("").unwrap(), Preset::default());
+}
+
+#[test]
+fn bad_presets_are_errors() {
+    for text in [
+        "palete = [[0, 0, 0], [0, 0, 0], [0, 0, 0]]",
+        "background = [2, 0, 0]",
+        "loudness_smoothing_ms = [-5, 250]",
+        "crossovers = [3000, 200]",
+        "shader = \"a.wgsl\"\nshader_source = \"// WGSL\"",
+        "crossovers = \"low\"",
+    ] {
+        assert!(Preset::parse(text).is_err(), "accepted {text:?}");
+    }
+}
+
+#[test]
+fn shader_is_read_next_to_the_preset() {
+    let dir = temp_dir("preset");
+    std::fs::write(dir.join("scene.wgsl"), "// scene").unwrap();
+    std::fs::write(dir.join("look.toml"), "shader = \"scene.wgsl\"\n").unwrap();
+    std::fs::write(dir.join("broken.toml"), "shader = \"missing.wgsl\"\n").unwrap();
+
+    let preset = Preset::load(&dir.join("look.toml"));
+    let broken = Preset::load(&dir.join("broken.toml"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let preset = preset.unwrap();
+    assert_eq!(preset.shader_source.as_deref(), Some("// scene"));
+    assert_eq!(preset.shader, Some(dir.join("scene.wgsl")));
+    assert!(broken.is_err());
+}
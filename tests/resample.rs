@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use time2freq::audio::{AudioPlayer, PlaybackEvent, ResampleQuality};
+
+mod common;
+
+use common::write_stereo_wav;
+
+const CHUNK_SIZE: usize = 1024;
+
+/// Play `input_frames` of `sample(i)` at `input_rate` into a WAV file at `output_rate`,
+/// returning the left channel of what came out.
+fn resample(
+    input_rate: u32,
+    output_rate: u32,
+    input_frames: usize,
+    sample: impl Fn(usize) -> f32,
+) -> Vec<f32> {
+    let name = format!("{input_rate}-{output_rate}-{input_frames}");
+    let input = write_stereo_wav(
+        &format!("resample-in-{name}"),
+        input_rate,
+        input_frames,
+        |i| [sample(i), -sample(i)],
+    );
+    let output = std::env::temp_dir().join(format!(
+        "time2freq-resample-out-{name}-{}.wav",
+        std::process::id()
+    ));
+
+    let mut player =
+        AudioPlayer::new_wav(&output, output_rate, 20, CHUNK_SIZE, ResampleQuality::Fast).unwrap();
+    player.play(input.clone());
+
+    let started = Instant::now();
+    let mut finished = false;
+    while !finished && started.elapsed() < Duration::from_secs(10) {
+        while let Some(event) = player.poll_event() {
+            finished |= matches!(event, PlaybackEvent::Finished);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(finished, "playback didn't finish");
+    drop(player);
+
+    let left = hound::WavReader::open(&output)
+        .unwrap()
+        .samples::<f32>()
+        .step_by(2)
+        .map(Result::unwrap)
+        .collect();
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    left
+}
+
+/// How many frames came out of playing `input_frames` of a ramp.
+fn resampled_frames(input_rate: u32, output_rate: u32, input_frames: usize) -> usize {
+    resample(input_rate, output_rate, input_frames, |i| {
+        (i % 100) as f32 / 500.0 - 0.1
+    })
+    .len()
+}
+
+/// Check the whole file came out, plus no more than the padding of one chunk.
+fn assert_ratio_kept(input_rate: u32, output_rate: u32, input_frames: usize) {
+    let output_frames = resampled_frames(input_rate, output_rate, input_frames);
+
+    let ratio = output_rate as f64 / input_rate as f64;
+    let expected = (input_frames as f64 * ratio) as usize;
+    let chunk_out = (CHUNK_SIZE as f64 * ratio).ceil() as usize;
+    assert!(
+        (expected..=expected + chunk_out).contains(&output_frames),
+        "{output_frames} frames, expected about {expected}"
+    );
+}
+
+#[test]
+fn partial_last_chunk_is_played() {
+    // Two and a bit chunks, so the tail doesn't fill a whole one.
+    assert_ratio_kept(44_100, 48_000, CHUNK_SIZE * 2 + 300);
+}
+
+#[test]
+fn downsampling_keeps_the_ratio() {
+    assert_ratio_kept(48_000, 44_100, CHUNK_SIZE * 2 + 300);
+}
+
+#[test]
+fn whole_chunks_keep_the_ratio() {
+    assert_ratio_kept(44_100, 48_000, CHUNK_SIZE * 4);
+    assert_ratio_kept(96_000, 48_000, CHUNK_SIZE * 4);
+}
+
+#[test]
+fn resampling_keeps_the_start_in_place() {
+    // Silence, then a step at 1000 frames, stretched by 48/44.1.
+    let output = resample(44_100, 48_000, CHUNK_SIZE * 4, |i| {
+        if i < 1000 {
+            0.0
+        } else {
+            0.5
+        }
+    });
+
+    let expected = (CHUNK_SIZE as f64 * 4.0 * 48_000.0 / 44_100.0).ceil() as usize;
+    assert_eq!(output.len(), expected);
+    assert!(output[0].abs() < 1e-3, "starts at {}", output[0]);
+    // A symmetric filter crosses halfway up the step right where it is.
+    let step = output.iter().position(|sample| *sample > 0.25).unwrap();
+    let at = (1000.0 * 48_000.0 / 44_100.0) as usize;
+    assert!(
+        (at - 2..=at + 2).contains(&step),
+        "step at {step}, expected {at}"
+    );
+}
@@ -0,0 +1,20 @@
+use time2freq::audio::sanitize;
+
+#[test]
+fn non_finite_samples_are_silenced() {
+    let mut samples = [0.5, f32::NAN, -0.5, f32::INFINITY, f32::NEG_INFINITY, 0.25];
+
+    assert_eq!(sanitize(&mut samples), 3);
+    assert_eq!(samples, [0.5, 0.0, -0.5, 0.0, 0.0, 0.25]);
+
+    let mean_square = samples.iter().map(|s| s.powi(2)).sum::<f32>() / samples.len() as f32;
+    assert!(mean_square.sqrt().is_finite());
+}
+
+#[test]
+fn finite_samples_are_untouched() {
+    let mut samples = [1.0, -1.0, 0.0, f32::MIN_POSITIVE];
+
+    assert_eq!(sanitize(&mut samples), 0);
+    assert_eq!(samples, [1.0, -1.0, 0.0, f32::MIN_POSITIVE]);
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use time2freq::Settings;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("time2freq-{name}-{}.toml", std::process::id()))
+}
+
+#[test]
+fn settings_round_trip() {
+    let path = temp_path("settings");
+    let settings = Settings {
+        volume: 0.5,
+        latency_ms: 40,
+        device: Some("speakers".into()),
+        shader: Some("scene.wgsl".into()),
+        window_size: Some((800, 600)),
+        ..Settings::default()
+    };
+
+    settings.save(&path).unwrap();
+    let loaded = Settings::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, settings);
+}
+
+#[test]
+fn missing_or_corrupt_settings_fall_back_to_defaults() {
+    let path = temp_path("missing");
+    assert_eq!(Settings::load(&path), Settings::default());
+
+    let path = temp_path("corrupt");
+    std::fs::write(&path, "volume = [not toml").unwrap();
+    let loaded = Settings::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, Settings::default());
+}
+
+#[test]
+fn missing_fields_take_defaults() {
+    let path = temp_path("partial");
+    std::fs::write(&path, "volume = 0.25\n").unwrap();
+    let loaded = Settings::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        loaded,
+        Settings {
+            volume: 0.25,
+            ..Settings::default()
+        }
+    );
+}
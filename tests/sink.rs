@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use time2freq::sink::{Device, OutputSink, WavFile};
+
+#[test]
+fn device_sink_fills_the_ring() {
+    let (producer, mut consumer) = rtrb::RingBuffer::<f32>::new(4);
+    let mut sink = Device::new(producer);
+
+    assert_eq!(sink.space(), 4);
+    sink.write(&[0.1, -0.1]).unwrap();
+    sink.write(&[0.2, -0.2]).unwrap();
+    assert_eq!(sink.space(), 0);
+    assert!(sink.write(&[0.3, -0.3]).is_err());
+
+    let played: Vec<f32> = std::iter::from_fn(|| consumer.pop().ok()).collect();
+    assert_eq!(played, [0.1, -0.1, 0.2, -0.2]);
+}
+
+#[test]
+fn wav_sink_writes_stereo_float() {
+    let path = std::env::temp_dir().join(format!("time2freq-sink-{}.wav", std::process::id()));
+    let mut sink = WavFile::create(&path, 48_000, Duration::from_millis(100)).unwrap();
+
+    assert!(!sink.buffers());
+    for frame in [[0.5, -0.5], [0.25, -0.25], [0.0, 1.0]] {
+        sink.write(&frame).unwrap();
+    }
+    sink.finalize().unwrap();
+
+    let mut reader = hound::WavReader::open(&path).unwrap();
+    let spec = reader.spec();
+    let samples: Vec<f32> = reader.samples::<f32>().map(Result::unwrap).collect();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.sample_rate, 48_000);
+    assert_eq!(samples, [0.5, -0.5, 0.25, -0.25, 0.0, 1.0]);
+}
+
+#[test]
+fn device_sink_wakes_when_drained() {
+    let (producer, mut consumer) = rtrb::RingBuffer::<f32>::new(2);
+    let mut sink = Device::new(producer);
+    sink.write(&[0.1, -0.1]).unwrap();
+
+    let unparker = sink.unparker().clone();
+    let drain = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        consumer.pop().unwrap();
+        consumer.pop().unwrap();
+        unparker.unpark();
+    });
+
+    let started = std::time::Instant::now();
+    while sink.space() < 2 {
+        sink.wait(Duration::from_secs(10));
+    }
+    drain.join().unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,155 @@
+use std::{f32::consts::PI, time::Duration};
+
+use time2freq::{log_buckets, MagnitudeScale, SmoothingMode, Spectrum, WindowFn};
+
+const FFT_SIZE: usize = 256;
+
+/// A full-scale sine centered on bin 8.
+fn push_sine(spectrum: &mut Spectrum) {
+    for i in 0..FFT_SIZE {
+        spectrum.push((2.0 * PI * 8.0 * i as f32 / FFT_SIZE as f32).sin());
+    }
+}
+
+fn push_silence(spectrum: &mut Spectrum) {
+    for _ in 0..FFT_SIZE {
+        spectrum.push(0.0);
+    }
+}
+
+#[test]
+fn full_scale_sine_reads_one() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    push_sine(&mut spectrum);
+
+    assert!((spectrum.process()[8] - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn peak_decay_falls_by_the_retained_fraction() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    spectrum.set_smoothing(SmoothingMode::PeakDecay(0.25));
+
+    push_sine(&mut spectrum);
+    let peak = spectrum.process_smoothed(Duration::from_millis(16))[8];
+    push_silence(&mut spectrum);
+    let half_second = spectrum.process_smoothed(Duration::from_millis(500))[8];
+
+    assert!((half_second - peak * 0.5).abs() < 1e-3);
+}
+
+#[test]
+fn ema_covers_most_of_the_step_in_one_time_constant() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    spectrum.set_smoothing(SmoothingMode::Ema(Duration::from_millis(100)));
+
+    push_sine(&mut spectrum);
+    let smoothed = spectrum.process_smoothed(Duration::from_millis(100))[8];
+
+    assert!((smoothed - (1.0 - (-1f32).exp())).abs() < 1e-3);
+}
+
+#[test]
+fn off_passes_magnitudes_through() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+
+    push_sine(&mut spectrum);
+    spectrum.process_smoothed(Duration::from_millis(16));
+    push_silence(&mut spectrum);
+
+    assert!(spectrum.process_smoothed(Duration::from_millis(16))[8].abs() < 1e-6);
+}
+
+#[test]
+fn smoothed_repeats_without_advancing() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    spectrum.set_smoothing(SmoothingMode::PeakDecay(0.25));
+
+    push_sine(&mut spectrum);
+    let peak = spectrum.process_smoothed(Duration::from_millis(16))[8];
+    push_silence(&mut spectrum);
+
+    assert_eq!(spectrum.smoothed()[8], peak);
+}
+
+#[test]
+fn wide_buckets_take_the_loudest_bin() {
+    let magnitudes = [0.0, 0.1, 0.5, 0.2, 0.9, 0.3];
+
+    assert_eq!(
+        log_buckets(&magnitudes, 100.0, &[100.0, 300.0, 600.0]),
+        [0.5, 0.9]
+    );
+}
+
+#[test]
+fn narrow_buckets_interpolate_between_bins() {
+    let magnitudes = [0.0, 1.0, 2.0];
+
+    // Centered a quarter and three quarters of the way from bin 1 to bin 2.
+    let buckets = log_buckets(&magnitudes, 100.0, &[120.0, 130.0, 170.0, 180.0]);
+
+    assert!((buckets[0] - 1.25).abs() < 1e-5);
+    assert!((buckets[1] - 1.5).abs() < 1e-5);
+    assert!((buckets[2] - 1.75).abs() < 1e-5);
+}
+
+#[test]
+fn linear_scale_normalizes_between_floor_and_ceiling() {
+    let scale = MagnitudeScale::Linear;
+
+    assert_eq!(scale.apply(0.5, 0.0, 1.0), 0.5);
+    assert_eq!(scale.apply(0.3, 0.2, 0.4), 0.5);
+    assert_eq!(scale.apply(2.0, 0.0, 1.0), 1.0);
+    assert_eq!(scale.apply(0.1, 0.2, 0.4), 0.0);
+}
+
+#[test]
+fn db_scale_is_linear_in_decibels() {
+    let scale = MagnitudeScale::Db;
+
+    assert!((scale.apply(1.0, -60.0, 0.0) - 1.0).abs() < 1e-6);
+    // -20 dB is a third of the way down 60 dB of range.
+    assert!((scale.apply(0.1, -60.0, 0.0) - 2.0 / 3.0).abs() < 1e-5);
+    assert_eq!(scale.apply(0.0, -60.0, 0.0), 0.0);
+}
+
+#[test]
+fn power_scale_curves_after_normalizing() {
+    let scale = MagnitudeScale::Power(0.5);
+
+    assert!((scale.apply(0.25, 0.0, 1.0) - 0.5).abs() < 1e-6);
+    assert!((scale.apply(0.5, 0.0, 2.0) - 0.5).abs() < 1e-6);
+    assert_eq!(scale.apply(1.0, 0.0, 1.0), 1.0);
+}
+
+#[test]
+fn resizing_the_fft_narrows_the_bins() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    spectrum.set_fft_size(FFT_SIZE * 2);
+    // Bin 8 of the old size is bin 16 of the new one.
+    for i in 0..FFT_SIZE * 2 {
+        spectrum.push((2.0 * PI * 8.0 * i as f32 / FFT_SIZE as f32).sin());
+    }
+
+    let magnitudes = spectrum.process();
+    assert_eq!(spectrum.fft_size(), FFT_SIZE * 2);
+    assert_eq!(magnitudes.len(), FFT_SIZE + 1);
+    assert!((magnitudes[16] - 1.0).abs() < 1e-3);
+    assert_eq!(spectrum.bin_width(48_000), 48_000.0 / (FFT_SIZE * 2) as f32);
+}
+
+#[test]
+fn repeat_process_follows_new_input_and_window() {
+    let mut spectrum = Spectrum::new(FFT_SIZE);
+    push_sine(&mut spectrum);
+    let first = spectrum.process().to_vec();
+
+    assert_eq!(spectrum.process(), &first[..]);
+
+    spectrum.set_window(WindowFn::Rectangular);
+    assert_ne!(spectrum.process(), &first[..]);
+
+    push_silence(&mut spectrum);
+    assert!(spectrum.process()[8].abs() < 1e-6);
+}
@@ -0,0 +1,84 @@
+use std::ptr::addr_of;
+
+use bytemuck::Zeroable;
+use time2freq::UniformRaw;
+
+/// Alignment and size of a WGSL type in the uniform address space.
+fn wgsl_type(ty: &str) -> (usize, usize) {
+    match ty {
+        "f32" | "u32" | "i32" => (4, 4),
+        "vec2<f32>" | "vec2<u32>" => (8, 8),
+        "vec3<f32>" | "vec3<u32>" => (16, 12),
+        "vec4<f32>" | "vec4<u32>" => (16, 16),
+        _ => {
+            // Uniform array elements are padded out to 16 bytes.
+            let (element, len) = ty
+                .strip_prefix("array<")
+                .and_then(|rest| rest.strip_suffix('>'))
+                .and_then(|rest| rest.rsplit_once(','))
+                .unwrap_or_else(|| panic!("no layout for {ty}"));
+            let (align, size) = wgsl_type(element.trim());
+            let stride = round_up(16, size);
+            (align.max(16), stride * len.trim().parse::<usize>().unwrap())
+        }
+    }
+}
+
+fn round_up(align: usize, offset: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+/// Field offsets and total size of `struct Uniform` in `shader.wgsl`.
+fn wgsl_layout() -> (Vec<(String, usize)>, usize) {
+    let source = include_str!("../src/shader.wgsl");
+    let body = source
+        .split("struct Uniform {")
+        .nth(1)
+        .and_then(|rest| rest.split('}').next())
+        .expect("shader declares struct Uniform");
+
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    let mut struct_align = 0;
+    for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let (name, ty) = line.trim_end_matches(',').split_once(':').unwrap();
+        let (align, size) = wgsl_type(ty.trim());
+        offset = round_up(align, offset);
+        fields.push((name.trim().to_string(), offset));
+        offset += size;
+        struct_align = struct_align.max(align);
+    }
+    (fields, round_up(struct_align, offset))
+}
+
+#[test]
+fn fields_match_the_shader() {
+    let raw = UniformRaw::zeroed();
+    let base = addr_of!(raw) as usize;
+    let rust = [
+        ("level", addr_of!(raw.level) as usize - base),
+        ("mouse_pos", addr_of!(raw.mouse_pos) as usize - base),
+        ("screen_size", addr_of!(raw.screen_size) as usize - base),
+        ("time", addr_of!(raw.time) as usize - base),
+        ("loudness", addr_of!(raw.loudness) as usize - base),
+        ("user", addr_of!(raw.user) as usize - base),
+        ("peak", addr_of!(raw.peak) as usize - base),
+        ("band_energy", addr_of!(raw.band_energy) as usize - base),
+        (
+            "spectrogram_row",
+            addr_of!(raw.spectrogram_row) as usize - base,
+        ),
+        ("stereo_mix", addr_of!(raw.stereo_mix) as usize - base),
+        ("beat", addr_of!(raw.beat) as usize - base),
+        ("mouse_down", addr_of!(raw.mouse_down) as usize - base),
+        ("palette", addr_of!(raw.palette) as usize - base),
+        ("level_fast", addr_of!(raw.level_fast) as usize - base),
+        ("level_slow", addr_of!(raw.level_slow) as usize - base),
+        ("bpm", addr_of!(raw.bpm) as usize - base),
+    ]
+    .map(|(name, offset)| (name.to_string(), offset));
+
+    let (wgsl, size) = wgsl_layout();
+    assert_eq!(wgsl, rust);
+    assert_eq!(std::mem::size_of::<UniformRaw>(), size);
+}